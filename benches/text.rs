@@ -0,0 +1,134 @@
+// Compares the rope that buffers store their text in with the vector of lines they used to use, on
+// a file large enough for the difference to matter.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+// The module doesn’t depend on anything else in the crate, so we can include it directly rather
+// than making it part of the public API.
+#[allow(dead_code)]
+#[path = "../src/text.rs"]
+mod text;
+
+use text::{Position, Text};
+
+// How buffers stored their text before they used a rope.
+struct Rows(Vec<String>);
+
+impl Rows {
+    fn from_file_contents(contents: &str) -> Self {
+        Self(contents.lines().map(ToString::to_string).collect())
+    }
+
+    fn insert(&mut self, at: Position, text: &str) {
+        let ending_segment = self.0[at.line].split_off(at.col);
+        let mut lines = text.split('\n');
+
+        self.0[at.line].push_str(lines.next().unwrap_or_default());
+
+        let mut line_nr = at.line;
+        for line in lines {
+            line_nr += 1;
+            self.0.insert(line_nr, line.to_string());
+        }
+
+        self.0[line_nr].push_str(&ending_segment);
+    }
+
+    fn remove(&mut self, start: Position, end: Position) {
+        let ending_segment = self.0[end.line][end.col..].to_string();
+
+        self.0.drain(start.line + 1..=end.line);
+        self.0[start.line].truncate(start.col);
+        self.0[start.line].push_str(&ending_segment);
+    }
+}
+
+// Roughly 14 MB of log-like lines.
+fn large_file() -> String {
+    (0..250_000)
+        .map(|idx| {
+            format!(
+                "2020-06-01 12:00:00 INFO request {} handled in {} ms\n",
+                idx,
+                idx % 97
+            )
+        })
+        .collect()
+}
+
+fn open(c: &mut Criterion) {
+    let contents = large_file();
+    let mut group = c.benchmark_group("open");
+    group.sample_size(10);
+
+    group.bench_function("rope", |b| {
+        b.iter(|| Text::from_file_contents(black_box(&contents)))
+    });
+    group.bench_function("rows", |b| {
+        b.iter(|| Rows::from_file_contents(black_box(&contents)))
+    });
+
+    group.finish();
+}
+
+// Splitting a line near the top of the file and joining it back up again, as happens when pressing
+// enter and then backspace.
+fn split_and_join_near_top(c: &mut Criterion) {
+    let contents = large_file();
+    let start = Position { line: 10, col: 5 };
+    let end = Position { line: 11, col: 0 };
+
+    let mut group = c.benchmark_group("split and join near top");
+
+    group.bench_function("rope", |b| {
+        let mut text = Text::from_file_contents(&contents);
+
+        b.iter(|| {
+            text.insert(black_box(start), "\n");
+            text.remove(black_box(start), black_box(end));
+        })
+    });
+    group.bench_function("rows", |b| {
+        let mut rows = Rows::from_file_contents(&contents);
+
+        b.iter(|| {
+            rows.insert(black_box(start), "\n");
+            rows.remove(black_box(start), black_box(end));
+        })
+    });
+
+    group.finish();
+}
+
+// Pasting a hundred lines into the middle of the file.
+fn paste_in_middle(c: &mut Criterion) {
+    let contents = large_file();
+    let pasted = "let x = 1;\n".repeat(100);
+    let at = Position {
+        line: 125_000,
+        col: 0,
+    };
+
+    let mut group = c.benchmark_group("paste in middle");
+    group.sample_size(20);
+
+    group.bench_function("rope", |b| {
+        b.iter_batched_ref(
+            || Text::from_file_contents(&contents),
+            |text| text.insert(black_box(at), &pasted),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("rows", |b| {
+        b.iter_batched_ref(
+            || Rows::from_file_contents(&contents),
+            |rows| rows.insert(black_box(at), &pasted),
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, open, split_and_join_near_top, paste_in_middle);
+criterion_main!(benches);
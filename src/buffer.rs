@@ -0,0 +1,743 @@
+use {
+    crate::{
+        graphemes,
+        history::{Change, EditKind, History},
+        languages::{self, Language},
+        render::{self, Highlights},
+        search::{self, SearchDirection},
+        text::{Position, Text},
+        themes::Theme,
+    },
+    regex::Regex,
+    std::{
+        borrow::Cow,
+        fs, io,
+        ops::Range,
+        path::{Path, PathBuf},
+    },
+};
+
+#[derive(Debug)]
+pub struct Buffer {
+    path: PathBuf,
+    text: Text,
+    line_nr: usize,
+    col_nr: usize,
+    // The other end of the selection from the cursor, if anything is selected. Any edit to the
+    // text clears it.
+    selection_anchor: Option<Position>,
+    history: History,
+    // The state of the history when the buffer was last saved (or opened).
+    saved_state: usize,
+    highlights: Highlights,
+    language: &'static Language,
+}
+
+#[derive(Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+    LineStart,
+    LineEnd,
+    BufferStart,
+    BufferEnd,
+}
+
+impl Buffer {
+    pub fn new(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        use anyhow::Context;
+
+        let path = path.as_ref();
+
+        // Paths that don’t exist yet are opened as new, empty files which get created on the first
+        // save.
+        let text = match fs::read_to_string(path) {
+            Ok(contents) => Text::from_file_contents(&contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Text::default(),
+            Err(e) => return Err(e).with_context(|| format!("failed to open {}", path.display())),
+        };
+
+        // The language determines how the buffer is highlighted.
+        let language = languages::detect(path, &text);
+
+        // Open new files with the cursor on the first column of the first line.
+        Ok(Self {
+            path: path.to_path_buf(),
+            text,
+            line_nr: 0,
+            col_nr: 0,
+            selection_anchor: None,
+            history: History::default(),
+            saved_state: 0,
+            highlights: Highlights::default(),
+            language,
+        })
+    }
+
+    // Saving is atomic: we write the new contents to a temporary file next to the original, and
+    // only once that has succeeded do we rename it over the original. This way a failed write
+    // (e.g. because the disk is full) never leaves a half-written file behind.
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        // Resolve symlinks so that we replace the file they point to, rather than the link itself.
+        // If the file doesn’t exist yet then there’s nothing to resolve, and no permissions to
+        // preserve either.
+        let (path, permissions) = match fs::canonicalize(&self.path) {
+            Ok(path) => {
+                let permissions = fs::metadata(&path)
+                    .with_context(|| format!("failed to read metadata of {}", path.display()))?
+                    .permissions();
+
+                (path, Some(permissions))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                ensure_parent_dir_exists(&self.path)?;
+                (self.path.clone(), None)
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to resolve {}", self.path.display()))
+            }
+        };
+
+        let temp_path = temp_path_for(&path);
+
+        let result = self.write_text(&temp_path, permissions).and_then(|()| {
+            fs::rename(&temp_path, &path)
+                .with_context(|| format!("failed to replace {}", path.display()))
+        });
+
+        // Don’t leave the temporary file lying around if anything went wrong.
+        match result {
+            Ok(()) => self.saved_state = self.history.state(),
+            Err(_) => {
+                let _ = fs::remove_file(&temp_path);
+            }
+        }
+
+        result
+    }
+
+    fn write_text(&self, path: &Path, permissions: Option<fs::Permissions>) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let file = fs::File::create(path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        let mut writer = io::BufWriter::new(file);
+
+        self.text.write_to(&mut writer)?;
+        let file = writer.into_inner()?;
+
+        // Make sure the contents have actually hit the disk before the rename makes them visible.
+        file.sync_all()
+            .with_context(|| format!("failed to write {}", path.display()))?;
+
+        if let Some(permissions) = permissions {
+            fs::set_permissions(path, permissions)
+                .with_context(|| format!("failed to set permissions of {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len_lines(&self) -> usize {
+        self.text.len_lines()
+    }
+
+    // Whether there are edits that haven’t been saved yet. Undoing back to the saved state counts
+    // as having no edits.
+    pub fn is_modified(&self) -> bool {
+        self.history.state() != self.saved_state
+    }
+
+    pub(crate) fn language_name(&self) -> &'static str {
+        self.language.name
+    }
+
+    // Highlighting depends on the theme, so switching themes means everything has to be
+    // highlighted again.
+    pub(crate) fn invalidate_highlights(&mut self) {
+        self.highlights.invalidate_from(0);
+    }
+
+    pub(crate) fn line_nr(&self) -> usize {
+        self.line_nr
+    }
+
+    pub(crate) fn col_nr(&self) -> usize {
+        self.col_nr
+    }
+
+    // Columns are counted in grapheme clusters rather than bytes or chars, so that the cursor
+    // never ends up in the middle of what the user sees as a single character.
+    pub(crate) fn line_len(&self, line_nr: usize) -> usize {
+        graphemes::count(&self.text.line(line_nr))
+    }
+
+    fn current_line_len(&self) -> usize {
+        self.line_len(self.line_nr)
+    }
+
+    // The terminal column the given position is displayed in, before accounting for scrolling.
+    pub(crate) fn display_col(&self, line_nr: usize, col_nr: usize) -> usize {
+        graphemes::display_col(&self.text.line(line_nr), col_nr)
+    }
+
+    // The column of the grapheme displayed in the given terminal column, which is the inverse of
+    // `display_col`.
+    pub(crate) fn col_at_display_col(&self, line_nr: usize, display_col: usize) -> usize {
+        graphemes::grapheme_idx_at_col(&self.text.line(line_nr), display_col)
+    }
+
+    pub(crate) fn grapheme_width(&self, line_nr: usize, col_nr: usize) -> Option<usize> {
+        graphemes::width_at(&self.text.line(line_nr), col_nr)
+    }
+
+    fn is_on_first_line(&self) -> bool {
+        self.line_nr == 0
+    }
+
+    fn is_on_last_line(&self) -> bool {
+        self.line_nr == self.text.len_lines() - 1
+    }
+
+    fn is_on_first_col(&self) -> bool {
+        self.col_nr == 0
+    }
+
+    fn is_on_last_col(&self) -> bool {
+        self.col_nr == self.current_line_len()
+    }
+
+    pub fn move_cursor(&mut self, direction: Direction) {
+        match direction {
+            Direction::Up => {
+                if !self.is_on_first_line() {
+                    self.line_nr -= 1;
+                    self.snap_cursor_to_eol();
+                }
+            }
+
+            Direction::Down => {
+                if !self.is_on_last_line() {
+                    self.line_nr += 1;
+                    self.snap_cursor_to_eol();
+                }
+            }
+
+            Direction::Left => {
+                if !self.is_on_first_col() {
+                    self.col_nr -= 1;
+                } else if !self.is_on_first_line() {
+                    self.line_nr -= 1;
+                    self.col_nr = self.current_line_len();
+                }
+            }
+
+            Direction::Right => {
+                if !self.is_on_last_col() {
+                    self.col_nr += 1;
+                } else if !self.is_on_last_line() {
+                    self.line_nr += 1;
+                    self.col_nr = 0;
+                }
+            }
+
+            // Going to the start of the line skips its indentation, unless the cursor is already
+            // at the end of it, so that pressing home twice goes all the way.
+            Direction::LineStart => {
+                let line = self.text.line(self.line_nr);
+                let indentation = &line[..line.len() - line.trim_start().len()];
+                let indentation_len = graphemes::count(indentation);
+
+                self.col_nr = if self.col_nr == indentation_len {
+                    0
+                } else {
+                    indentation_len
+                };
+            }
+
+            Direction::LineEnd => self.col_nr = self.current_line_len(),
+
+            Direction::BufferStart => {
+                self.line_nr = 0;
+                self.col_nr = 0;
+            }
+
+            Direction::BufferEnd => {
+                self.line_nr = self.text.len_lines() - 1;
+                self.col_nr = self.current_line_len();
+            }
+        }
+    }
+
+    // Moves the cursor to the nearest match of the query, returning whether the search had to wrap
+    // around the end of the buffer to find it, or `None` if there are no matches at all.
+    pub(crate) fn find(
+        &mut self,
+        query: &str,
+        direction: SearchDirection,
+        include_cursor: bool,
+    ) -> Option<bool> {
+        let (position, is_wrapped) =
+            search::find(&self.text, query, self.cursor(), direction, include_cursor)?;
+        self.set_cursor(position);
+
+        Some(is_wrapped)
+    }
+
+    pub(crate) fn matches_in_line(&self, line_nr: usize, regex: &Regex) -> Vec<Range<usize>> {
+        search::matches_in_line(&self.text.line(line_nr), regex)
+    }
+
+    // The whole of the text as a single string, and offsets into it, for searches that can span
+    // several lines.
+    pub(crate) fn contents(&self) -> String {
+        self.text.contents()
+    }
+
+    // Looking at the text around an offset doesn’t involve copying the rest of it, unlike
+    // `contents`.
+    pub(crate) fn char_at(&self, byte_idx: usize) -> Option<char> {
+        self.text.char_at(byte_idx)
+    }
+
+    pub(crate) fn char_before(&self, byte_idx: usize) -> Option<char> {
+        self.text.char_before(byte_idx)
+    }
+
+    // The line containing the given offset (without its line feed), and the offset it starts at.
+    pub(crate) fn line_at(&self, byte_idx: usize) -> (usize, Cow<'_, str>) {
+        let position = self.text.position(byte_idx);
+        (byte_idx - position.col, self.text.line(position.line))
+    }
+
+    pub(crate) fn text_in(&self, range: Range<usize>) -> String {
+        self.text.slice(
+            self.text.position(range.start),
+            self.text.position(range.end),
+        )
+    }
+
+    pub(crate) fn len_bytes(&self) -> usize {
+        self.text.len_bytes()
+    }
+
+    pub(crate) fn move_cursor_to_byte(&mut self, byte_idx: usize) {
+        self.set_cursor(self.text.position(byte_idx));
+    }
+
+    pub(crate) fn cursor_byte_idx(&self) -> usize {
+        self.text.byte_idx(self.cursor())
+    }
+
+    // The byte offset of the given line and column, with the column clamped to the end of the
+    // line.
+    pub(crate) fn byte_idx_at(&self, line_nr: usize, col_nr: usize) -> usize {
+        let col = graphemes::byte_idx(&self.text.line(line_nr), col_nr);
+        self.text.byte_idx(Position { line: line_nr, col })
+    }
+
+    // The part of the given line that falls within a range of byte offsets, relative to the start
+    // of the line.
+    pub(crate) fn range_in_line(&self, line_nr: usize, range: &Range<usize>) -> Range<usize> {
+        let line_start = self.byte_idx_at(line_nr, 0);
+        let line_end = line_start + self.text.line_len(line_nr);
+
+        let start = range.start.clamp(line_start, line_end);
+        let end = range.end.clamp(line_start, line_end);
+
+        start - line_start..end - line_start
+    }
+
+    // Replaces the text between the given byte offsets, leaving the cursor after the replacement.
+    pub(crate) fn replace(&mut self, range: Range<usize>, replacement: &str) {
+        let start = self.text.position(range.start);
+        let end = self.text.position(range.end);
+        self.set_cursor(start);
+
+        if start != end {
+            self.edit(
+                Change::Delete {
+                    at: start,
+                    text: self.text.slice(start, end),
+                },
+                EditKind::Other,
+            );
+        }
+
+        if !replacement.is_empty() {
+            self.edit(
+                Change::Insert {
+                    at: start,
+                    text: replacement.to_string(),
+                },
+                EditKind::Other,
+            );
+        }
+    }
+
+    // Starts selecting from the cursor, unless something is already selected, in which case
+    // moving the cursor extends the existing selection.
+    pub(crate) fn start_selection(&mut self) {
+        if self.selection_anchor.is_none() {
+            self.selection_anchor = Some(self.cursor());
+        }
+    }
+
+    pub(crate) fn clear_selection(&mut self) {
+        self.selection_anchor = None;
+    }
+
+    // The selected text as byte offsets, from whichever of the anchor and the cursor comes first
+    // up to (but not including) the other.
+    pub(crate) fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.text.byte_idx(self.selection_anchor?);
+        let cursor = self.cursor_byte_idx();
+
+        Some(anchor.min(cursor)..anchor.max(cursor)).filter(|range| !range.is_empty())
+    }
+
+    pub(crate) fn selected_text(&self) -> Option<String> {
+        Some(self.text_in(self.selection()?))
+    }
+
+    // Deletes the selected text, returning whether there was any.
+    pub(crate) fn delete_selection(&mut self) -> bool {
+        match self.selection() {
+            Some(range) => {
+                self.replace(range, "");
+                true
+            }
+            None => {
+                self.clear_selection();
+                false
+            }
+        }
+    }
+
+    // Edits made between these two calls are undone and redone all at once.
+    pub(crate) fn begin_edit_group(&mut self) {
+        self.history.begin_group();
+    }
+
+    pub(crate) fn end_edit_group(&mut self) {
+        self.history.end_group();
+    }
+
+    // Moves the cursor to the given position, or as close to it as the text allows.
+    pub(crate) fn move_cursor_to(&mut self, line_nr: usize, col_nr: usize) {
+        self.line_nr = line_nr.min(self.text.len_lines() - 1);
+        self.col_nr = col_nr;
+        self.snap_cursor_to_eol();
+    }
+
+    fn snap_cursor_to_eol(&mut self) {
+        let current_line_len = self.current_line_len();
+
+        if self.col_nr >= current_line_len {
+            self.col_nr = current_line_len;
+        }
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        self.edit(
+            Change::Insert {
+                at: self.cursor(),
+                text: c.to_string(),
+            },
+            EditKind::Typing,
+        );
+    }
+
+    pub fn insert_newline(&mut self) {
+        self.edit(
+            Change::Insert {
+                at: self.cursor(),
+                text: "\n".to_string(),
+            },
+            EditKind::Other,
+        );
+    }
+
+    pub fn backspace(&mut self) {
+        if self.is_on_first_col() && self.is_on_first_line() {
+            return;
+        }
+
+        // Deleting backwards from the first column removes the line break at the end of the line
+        // above, thereby joining the two lines.
+        let end = self.cursor();
+        let start = if self.is_on_first_col() {
+            Position {
+                line: end.line - 1,
+                col: self.text.line_len(end.line - 1),
+            }
+        } else {
+            Position {
+                line: end.line,
+                col: graphemes::byte_idx(&self.text.line(end.line), self.col_nr - 1),
+            }
+        };
+
+        self.edit(
+            Change::Delete {
+                at: start,
+                text: self.text.slice(start, end),
+            },
+            EditKind::Deleting,
+        );
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(step) = self.history.undo() {
+            for change in step.changes.iter().rev() {
+                self.apply(&change.inverse());
+            }
+
+            self.set_cursor(step.cursor_before);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(step) = self.history.redo() {
+            for change in &step.changes {
+                self.apply(change);
+            }
+
+            self.set_cursor(step.cursor_after);
+        }
+    }
+
+    // All modifications to the text go through here, so that they can be undone.
+    fn edit(&mut self, change: Change, kind: EditKind) {
+        let cursor_before = self.cursor();
+        let cursor_after = self.apply(&change);
+        self.set_cursor(cursor_after);
+
+        self.history
+            .record(change, cursor_before, cursor_after, kind);
+    }
+
+    // Applies a change to the text without recording it, returning the position the cursor should
+    // be placed at afterwards.
+    fn apply(&mut self, change: &Change) -> Position {
+        let (Change::Insert { at, .. } | Change::Delete { at, .. }) = change;
+        self.highlights.invalidate_from(at.line);
+        self.selection_anchor = None;
+
+        match change {
+            Change::Insert { at, text } => {
+                self.text.insert(*at, text);
+                end_of_text(*at, text)
+            }
+            Change::Delete { at, text } => {
+                self.text.remove(*at, end_of_text(*at, text));
+                *at
+            }
+        }
+    }
+
+    // Edits are tracked in terms of byte positions, whereas the cursor’s column is a grapheme
+    // index, so we have to convert between the two.
+    fn cursor(&self) -> Position {
+        Position {
+            line: self.line_nr,
+            col: graphemes::byte_idx(&self.text.line(self.line_nr), self.col_nr),
+        }
+    }
+
+    fn set_cursor(&mut self, position: Position) {
+        self.line_nr = position.line;
+        self.col_nr = graphemes::grapheme_idx(&self.text.line(self.line_nr), position.col);
+    }
+
+    // Only the lines that are on screen are highlighted, starting at the top of the view and with
+    // only enough lines to fill it.
+    pub(crate) fn highlighted_lines(
+        &mut self,
+        range: Range<usize>,
+        theme: &Theme,
+    ) -> &[render::Line] {
+        self.highlights
+            .get(&self.text, range, self.language.highlighter, theme)
+    }
+}
+
+// Finds the position just after the given text if it were to be inserted at the given position.
+fn end_of_text(at: Position, text: &str) -> Position {
+    match text.rfind('\n') {
+        Some(idx) => Position {
+            line: at.line + text.matches('\n').count(),
+            col: text.len() - idx - 1,
+        },
+        None => Position {
+            line: at.line,
+            col: at.col + text.len(),
+        },
+    }
+}
+
+pub(crate) fn new_file_message(path: &Path) -> String {
+    match missing_parent_dir(path) {
+        Some(parent) => format!(
+            "New file (directory {} does not exist; create it before saving)",
+            parent.display()
+        ),
+        None => "New file".to_string(),
+    }
+}
+
+fn ensure_parent_dir_exists(path: &Path) -> anyhow::Result<()> {
+    match missing_parent_dir(path) {
+        Some(parent) => Err(anyhow::anyhow!(
+            "cannot save {}: directory {} does not exist",
+            path.display(),
+            parent.display()
+        )),
+        None => Ok(()),
+    }
+}
+
+fn missing_parent_dir(path: &Path) -> Option<&Path> {
+    // A bare file name has an empty parent, which refers to the current directory.
+    path.parent()
+        .filter(|parent| !parent.as_os_str().is_empty() && !parent.is_dir())
+}
+
+// The temporary file used while saving lives in the same directory as the file being saved, since
+// renaming only works atomically within a single filesystem.
+fn temp_path_for(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.se-tmp", file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::env};
+
+    // An empty directory of its own for each test, since they run in parallel.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("se-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn dir_entries(dir: &Path) -> Vec<String> {
+        let mut entries: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        entries.sort();
+
+        entries
+    }
+
+    #[test]
+    fn saving_replaces_the_file_and_leaves_no_temporary_file() {
+        let dir = temp_dir("save");
+        let path = dir.join("file.txt");
+        fs::write(&path, "old\n").unwrap();
+
+        let mut buffer = Buffer::new(&path).unwrap();
+        buffer.replace(0..3, "new");
+        assert!(buffer.is_modified());
+
+        buffer.save().unwrap();
+        assert!(!buffer.is_modified());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert_eq!(dir_entries(&dir), vec!["file.txt"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn new_files_are_created_on_the_first_save() {
+        let dir = temp_dir("new");
+        let path = dir.join("file.txt");
+
+        let mut buffer = Buffer::new(&path).unwrap();
+        buffer.replace(0..0, "text");
+        buffer.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "text\n");
+        assert_eq!(dir_entries(&dir), vec!["file.txt"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_keeps_the_permissions_of_the_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("permissions");
+        let path = dir.join("script.sh");
+        fs::write(&path, "echo\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+
+        let mut buffer = Buffer::new(&path).unwrap();
+        buffer.replace(0..0, "#!/bin/sh\n");
+        buffer.save().unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saving_into_a_missing_directory_is_an_error() {
+        let dir = temp_dir("missing");
+        let path = dir.join("missing").join("file.txt");
+
+        let mut buffer = Buffer::new(&path).unwrap();
+        buffer.replace(0..0, "text");
+
+        let error = buffer.save().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "cannot save {}: directory {} does not exist",
+                path.display(),
+                dir.join("missing").display()
+            )
+        );
+        assert!(buffer.is_modified());
+        assert!(dir_entries(&dir).is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_saves_are_reported_and_clean_up_after_themselves() {
+        let dir = temp_dir("failed");
+        let path = dir.join("file.txt");
+
+        let mut buffer = Buffer::new(&path).unwrap();
+        buffer.replace(0..0, "text");
+
+        // A file can’t be renamed over a directory.
+        fs::create_dir(&path).unwrap();
+
+        let error = buffer.save().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "failed to replace {}",
+                path.canonicalize().unwrap().display()
+            )
+        );
+        assert!(buffer.is_modified());
+        assert_eq!(dir_entries(&dir), vec!["file.txt"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+// The system clipboard, which yanked and pasted text can optionally go through so that it can be
+// shared with other programs. There are two ways of getting at it:
+//
+// - OSC 52 escape sequences ask the terminal to put text on the clipboard, which works even over
+//   SSH. Terminals rarely allow reading the clipboard back this way, so pasting uses the text
+//   copied last (or the terminal’s own paste).
+// - External commands such as `xclip`, `wl-copy` and `pbcopy` are given the text on their
+//   standard input, and a second command prints the clipboard’s contents for pasting.
+//
+// Commands are split on whitespace, without any shell quoting.
+
+use {
+    anyhow::Context,
+    std::{
+        env,
+        io::{self, Write},
+        path::Path,
+        process::{self, Stdio},
+    },
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) enum Clipboard {
+    // Yanked text stays inside the editor.
+    #[default]
+    Internal,
+    Osc52,
+    Command {
+        copy: Vec<String>,
+        paste: Option<Vec<String>>,
+    },
+}
+
+impl Clipboard {
+    // Picks whichever way of getting at the clipboard is available: a clipboard command for the
+    // display server or OS if one is installed, and otherwise OSC 52 over SSH, where there is no
+    // display server to talk to.
+    pub(crate) fn detect() -> Self {
+        let command = |copy: &str, paste: &str| Self::Command {
+            copy: split_command(copy),
+            paste: Some(split_command(paste)),
+        };
+
+        if cfg!(target_os = "macos") && is_on_path("pbcopy") {
+            command("pbcopy", "pbpaste")
+        } else if env::var_os("WAYLAND_DISPLAY").is_some() && is_on_path("wl-copy") {
+            command("wl-copy", "wl-paste --no-newline")
+        } else if env::var_os("DISPLAY").is_some() && is_on_path("xclip") {
+            command(
+                "xclip -selection clipboard",
+                "xclip -selection clipboard -o",
+            )
+        } else if env::var_os("DISPLAY").is_some() && is_on_path("xsel") {
+            command("xsel --clipboard --input", "xsel --clipboard --output")
+        } else if env::var_os("SSH_TTY").is_some() {
+            Self::Osc52
+        } else {
+            Self::Internal
+        }
+    }
+
+    pub(crate) fn copy(&self, text: &str) -> anyhow::Result<()> {
+        match self {
+            Self::Internal => Ok(()),
+            Self::Osc52 => {
+                let mut stdout = io::stdout();
+                write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+                stdout.flush()?;
+
+                Ok(())
+            }
+            Self::Command { copy, .. } => {
+                // Clipboard commands on X11 stay around in the background to serve the clipboard,
+                // so we can’t wait for their output to be closed, only for them to exit.
+                let mut child = command(copy)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .with_context(|| format!("failed to run `{}`", copy.join(" ")))?;
+
+                // Wait for the command even if it stopped reading, so as not to leave a zombie.
+                let written = child.stdin.take().unwrap().write_all(text.as_bytes());
+                let status = child.wait()?;
+                written?;
+
+                if !status.success() {
+                    anyhow::bail!("`{}` failed ({})", copy.join(" "), status);
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    // The clipboard’s contents, if they can be read.
+    pub(crate) fn paste(&self) -> anyhow::Result<Option<String>> {
+        let paste = match self {
+            Self::Command {
+                paste: Some(paste), ..
+            } => paste,
+            _ => return Ok(None),
+        };
+
+        let output = command(paste)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .with_context(|| format!("failed to run `{}`", paste.join(" ")))?;
+
+        if !output.status.success() {
+            anyhow::bail!("`{}` failed ({})", paste.join(" "), output.status);
+        }
+
+        Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+    }
+}
+
+pub(crate) fn split_command(command: &str) -> Vec<String> {
+    command
+        .split_whitespace()
+        .map(ToString::to_string)
+        .collect()
+}
+
+fn command(args: &[String]) -> process::Command {
+    let mut command = process::Command::new(&args[0]);
+    command.args(&args[1..]);
+    command
+}
+
+fn is_on_path(program: &str) -> bool {
+    env::var_os("PATH").is_some_and(|path| {
+        env::split_paths(&path).any(|dir| Path::new(&dir).join(program).is_file())
+    })
+}
+
+// OSC 52 takes the text encoded as Base64.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0, |n, (idx, byte)| n | u32::from(*byte) << (16 - 8 * idx));
+
+        // Every byte of input gives at least one more character of output, and the rest is padded.
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                encoded.push(char::from(ALPHABET[(n >> (18 - 6 * idx) & 63) as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The test vectors from RFC 4648.
+    #[test]
+    fn base64_pads_to_a_multiple_of_four() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(b"fooba"), "Zm9vYmE=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn base64_uses_the_whole_alphabet() {
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+        assert_eq!(base64("é\n".as_bytes()), "w6kK");
+    }
+
+    #[test]
+    fn commands_are_split_on_whitespace() {
+        assert_eq!(
+            split_command("  xclip -selection   clipboard "),
+            vec!["xclip", "-selection", "clipboard"]
+        );
+        assert!(split_command(" ").is_empty());
+    }
+}
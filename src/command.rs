@@ -0,0 +1,311 @@
+// The commands that can be typed into the command line, which is opened with C-p. Commands are a
+// name followed by an argument, much like Vim’s ex commands.
+
+use {
+    crate::view::LineNumbers,
+    std::{fs, path::PathBuf},
+};
+
+#[derive(Debug)]
+pub(crate) enum Command {
+    Open(PathBuf),
+    Write,
+    // Forcing it quits even if there are unsaved changes.
+    Quit { is_forced: bool },
+    Goto(usize),
+    Theme(String),
+    Set(Setting),
+    Keys,
+    Replace,
+}
+
+// Options that can be changed with `set`.
+#[derive(Debug)]
+pub(crate) enum Setting {
+    LineNumbers(LineNumbers),
+}
+
+// The full name of every command, which is what gets completed, along with its abbreviations.
+const COMMANDS: &[(&str, &[&str])] = &[
+    ("goto", &["g"]),
+    ("keys", &[]),
+    ("open", &["o", "e", "edit"]),
+    ("quit", &["q"]),
+    ("replace", &["s"]),
+    ("set", &[]),
+    ("theme", &[]),
+    ("write", &["w"]),
+];
+
+// The name of every option, along with the values it can be set to.
+const OPTIONS: &[(&str, &[&str])] = &[("line-numbers", &["off", "absolute", "relative"])];
+
+pub(crate) fn parse(input: &str) -> anyhow::Result<Command> {
+    let (name, argument) = split(input.trim());
+
+    // A bare line number goes to that line, as in Vim.
+    if let Ok(line_nr) = name.parse() {
+        if argument.is_empty() {
+            return goto(line_nr);
+        }
+    }
+
+    // Quitting can be forced with a `!` after the name, as in Vim.
+    let (name, is_forced) = match name.strip_suffix('!') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+
+    let name = full_name(name).ok_or_else(|| anyhow::anyhow!("unknown command `{}`", name))?;
+
+    if is_forced && name != "quit" {
+        anyhow::bail!("`{}` can’t be forced", name);
+    }
+
+    match (name, argument) {
+        ("open", "") => anyhow::bail!("`open` needs a path to open"),
+        ("open", path) => Ok(Command::Open(PathBuf::from(path))),
+        ("write", "") => Ok(Command::Write),
+        ("quit", "") => Ok(Command::Quit { is_forced }),
+        ("keys", "") => Ok(Command::Keys),
+        ("replace", "") => Ok(Command::Replace),
+        ("goto", line_nr) => match line_nr.parse() {
+            Ok(line_nr) => goto(line_nr),
+            Err(_) => anyhow::bail!("`goto` needs a line number, not `{}`", line_nr),
+        },
+        ("theme", "") => anyhow::bail!("`theme` needs the name of a theme"),
+        ("theme", theme) => Ok(Command::Theme(theme.to_string())),
+        ("set", setting) => parse_setting(setting).map(Command::Set),
+        (name, _) => anyhow::bail!("`{}` doesn’t take an argument", name),
+    }
+}
+
+// Line numbers are counted from one, as they are in the status line and the gutter.
+fn goto(line_nr: usize) -> anyhow::Result<Command> {
+    if line_nr == 0 {
+        anyhow::bail!("line numbers start at 1");
+    }
+
+    Ok(Command::Goto(line_nr - 1))
+}
+
+// Options are set with either `set name value` or `set name=value`.
+fn parse_setting(input: &str) -> anyhow::Result<Setting> {
+    let (name, value) = match input.find('=') {
+        Some(idx) => (input[..idx].trim(), input[idx + 1..].trim()),
+        None => split(input),
+    };
+
+    if !OPTIONS.iter().any(|(option, _)| *option == name) {
+        anyhow::bail!(
+            "unknown option `{}` (available options: {})",
+            name,
+            OPTIONS
+                .iter()
+                .map(|(option, _)| *option)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    if value.is_empty() {
+        anyhow::bail!("`{}` needs a value", name);
+    }
+
+    match name {
+        "line-numbers" => Ok(Setting::LineNumbers(value.parse()?)),
+        _ => unreachable!(),
+    }
+}
+
+// Completes the input of the command line, returning every possible completion of it in full.
+pub(crate) fn complete(input: &str, theme_names: &[&str]) -> Vec<String> {
+    let (name, argument) = split(input.trim_start());
+
+    // Until there is a space after the command’s name, it’s the name that is being completed.
+    if !input.trim_start().contains(char::is_whitespace) {
+        return COMMANDS
+            .iter()
+            .map(|(command, _)| *command)
+            .filter(|command| command.starts_with(name))
+            .map(|command| format!("{} ", command))
+            .collect();
+    }
+
+    let name = match full_name(name) {
+        Some(name) => name,
+        None => return Vec::new(),
+    };
+
+    let completions = match name {
+        "open" => complete_path(argument),
+        "theme" => starting_with(theme_names.iter().copied(), argument),
+        "set" => complete_setting(argument),
+        _ => Vec::new(),
+    };
+
+    completions
+        .into_iter()
+        .map(|completion| format!("{} {}", name, completion))
+        .collect()
+}
+
+fn complete_setting(input: &str) -> Vec<String> {
+    let (name, value) = split(input);
+
+    if !input.contains(char::is_whitespace) {
+        return starting_with(OPTIONS.iter().map(|(option, _)| *option), name)
+            .into_iter()
+            .map(|option| format!("{} ", option))
+            .collect();
+    }
+
+    match OPTIONS.iter().find(|(option, _)| *option == name) {
+        Some((_, values)) => starting_with(values.iter().copied(), value)
+            .into_iter()
+            .map(|value| format!("{} {}", name, value))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+// Completes the last component of a path with the names of the entries in its directory.
+// Directories are completed with a trailing slash, so that their contents can be completed next.
+// Hidden files are left out unless the user has started typing their name.
+pub(crate) fn complete_path(input: &str) -> Vec<String> {
+    let (dir, prefix) = match input.rfind('/') {
+        Some(idx) => (&input[..=idx], &input[idx + 1..]),
+        None => ("", input),
+    };
+
+    let entries = match fs::read_dir(if dir.is_empty() { "." } else { dir }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut completions: Vec<_> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+
+            if !name.starts_with(prefix) || name.starts_with('.') && !prefix.starts_with('.') {
+                return None;
+            }
+
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect();
+
+    completions.sort();
+
+    completions
+}
+
+fn starting_with<'a>(candidates: impl Iterator<Item = &'a str>, prefix: &str) -> Vec<String> {
+    candidates
+        .filter(|candidate| candidate.starts_with(prefix))
+        .map(ToString::to_string)
+        .collect()
+}
+
+fn full_name(name: &str) -> Option<&'static str> {
+    COMMANDS
+        .iter()
+        .find(|(command, abbreviations)| *command == name || abbreviations.contains(&name))
+        .map(|(command, _)| *command)
+}
+
+// Splits the input into the first word and whatever follows it.
+fn split(input: &str) -> (&str, &str) {
+    match input.find(char::is_whitespace) {
+        Some(idx) => (&input[..idx], input[idx..].trim()),
+        None => (input, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_are_parsed_by_name_or_abbreviation() {
+        assert!(matches!(parse("write"), Ok(Command::Write)));
+        assert!(matches!(
+            parse(" q "),
+            Ok(Command::Quit { is_forced: false })
+        ));
+        assert!(
+            matches!(parse("e src/lib.rs"), Ok(Command::Open(path)) if path.to_str() == Some("src/lib.rs"))
+        );
+        assert!(matches!(parse("theme gruvbox"), Ok(Command::Theme(theme)) if theme == "gruvbox"));
+        assert!(matches!(parse("s"), Ok(Command::Replace)));
+    }
+
+    #[test]
+    fn only_quitting_can_be_forced() {
+        assert!(matches!(
+            parse("quit!"),
+            Ok(Command::Quit { is_forced: true })
+        ));
+        assert!(matches!(parse("q!"), Ok(Command::Quit { is_forced: true })));
+        assert_eq!(
+            parse("w!").unwrap_err().to_string(),
+            "`write` can’t be forced"
+        );
+    }
+
+    #[test]
+    fn line_numbers_count_from_one() {
+        assert!(matches!(parse("12"), Ok(Command::Goto(11))));
+        assert!(matches!(parse("goto 1"), Ok(Command::Goto(0))));
+        assert!(parse("0").is_err());
+        assert!(parse("goto x").is_err());
+    }
+
+    #[test]
+    fn settings_take_a_value_with_or_without_equals() {
+        for input in &["set line-numbers relative", "set line-numbers=relative"] {
+            assert!(matches!(
+                parse(input),
+                Ok(Command::Set(Setting::LineNumbers(LineNumbers::Relative)))
+            ));
+        }
+
+        assert!(parse("set line-numbers").is_err());
+        assert!(parse("set tabs 4").is_err());
+    }
+
+    #[test]
+    fn bad_commands_are_errors() {
+        assert_eq!(
+            parse("frobnicate").unwrap_err().to_string(),
+            "unknown command `frobnicate`"
+        );
+        assert!(parse("open").is_err());
+        assert!(parse("quit now").is_err());
+    }
+
+    #[test]
+    fn names_are_completed_in_full() {
+        assert_eq!(complete("", &[]).len(), COMMANDS.len());
+        assert_eq!(complete("t", &[]), vec!["theme "]);
+        assert_eq!(complete("  wr", &[]), vec!["write "]);
+        assert!(complete("x", &[]).is_empty());
+    }
+
+    #[test]
+    fn arguments_are_completed_after_the_full_name() {
+        assert_eq!(
+            complete("theme g", &["gruvbox", "github", "solarized"]),
+            vec!["theme gruvbox", "theme github"]
+        );
+        assert_eq!(complete("set li", &[]), vec!["set line-numbers "]);
+        assert_eq!(
+            complete("set line-numbers a", &[]),
+            vec!["set line-numbers absolute"]
+        );
+        assert!(complete("quit x", &[]).is_empty());
+    }
+}
@@ -0,0 +1,304 @@
+// The user’s settings are kept in `config.toml` in the config directory, and look like this:
+//
+//     modal = true
+//
+//     [keys]
+//     "C-w s" = "split-horizontal"
+//     "C-w v" = "split-vertical"
+//     "C-z" = "none"
+//
+//     [clipboard]
+//     method = "command"
+//     copy = "xclip -selection clipboard"
+//     paste = "xclip -selection clipboard -o"
+//
+// Each entry under `keys` binds a key or chord to the named action, or unbinds it if the action
+// is `none`. No two entries can bind the same keys, or keys that the other starts with. Anything
+// that isn’t set keeps its default. Setting `modal` turns on Vi-style modal editing, whose keys
+// aren’t affected by the `keys` table (other than those Vi has no use for).
+//
+// The clipboard `method` is one of `internal` (the default), `osc52`, `command` (which needs a
+// `copy` command, and optionally a `paste` one) or `auto`, which picks whichever is available.
+
+use {
+    crate::{
+        clipboard::{self, Clipboard},
+        keymap::{self, Key, Keymap},
+    },
+    anyhow::Context,
+    std::{fs, io, path::PathBuf},
+    toml::value::{Table, Value},
+};
+
+// Everything the user can configure lives in a single directory, e.g. `~/.config/se` on Linux.
+pub(crate) fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("se"))
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Config {
+    pub(crate) keymap: Keymap,
+    pub(crate) is_modal: bool,
+    pub(crate) clipboard: Clipboard,
+}
+
+impl Config {
+    // A missing config file is the same as an empty one.
+    pub(crate) fn load() -> anyhow::Result<Self> {
+        let path = match dir() {
+            Some(dir) => dir.join("config.toml"),
+            None => return Ok(Self::default()),
+        };
+
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+
+        Self::parse(&source).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    fn parse(source: &str) -> anyhow::Result<Self> {
+        let table: Table = toml::from_str(source)?;
+        let mut config = Self::default();
+
+        for (key, value) in &table {
+            match key.as_str() {
+                "modal" => {
+                    config.is_modal = value
+                        .as_bool()
+                        .ok_or_else(|| anyhow::anyhow!("`modal`: expected `true` or `false`"))?
+                }
+                "keys" => {
+                    // Bindings are applied in order of their keys, so if two of them overlapped
+                    // one would silently replace the other.
+                    let mut bound: Vec<(Vec<Key>, &str)> = Vec::new();
+
+                    for (keys, action) in as_table(value, "keys")? {
+                        let action = action.as_str().ok_or_else(|| {
+                            anyhow::anyhow!("`keys.{}`: expected the name of an action", keys)
+                        })?;
+
+                        let parsed =
+                            keymap::parse_keys(keys).with_context(|| format!("`keys.{}`", keys))?;
+
+                        if let Some((_, other)) = bound
+                            .iter()
+                            .find(|(other, _)| keymap::overlap(other, &parsed))
+                        {
+                            anyhow::bail!("`keys.{}`: conflicts with `keys.{}`", keys, other);
+                        }
+
+                        config
+                            .keymap
+                            .bind(keys, action)
+                            .with_context(|| format!("`keys.{}`", keys))?;
+                        bound.push((parsed, keys));
+                    }
+                }
+                "clipboard" => config.clipboard = parse_clipboard(as_table(value, "clipboard")?)?,
+                _ => anyhow::bail!(
+                    "`{}`: unknown key (expected `modal`, `keys` or `clipboard`)",
+                    key
+                ),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_clipboard(table: &Table) -> anyhow::Result<Clipboard> {
+    let string = |key: &str| -> anyhow::Result<Option<&str>> {
+        match table.get(key) {
+            Some(value) => value
+                .as_str()
+                .map(Some)
+                .ok_or_else(|| anyhow::anyhow!("`clipboard.{}`: expected a string", key)),
+            None => Ok(None),
+        }
+    };
+
+    for key in table.keys() {
+        if !["method", "copy", "paste"].contains(&key.as_str()) {
+            anyhow::bail!(
+                "`clipboard.{}`: unknown key (expected `method`, `copy` or `paste`)",
+                key
+            );
+        }
+    }
+
+    let command = |key: &str| -> anyhow::Result<Option<Vec<String>>> {
+        match string(key)?.map(clipboard::split_command) {
+            Some(command) if command.is_empty() => {
+                anyhow::bail!("`clipboard.{}`: empty command", key)
+            }
+            command => Ok(command),
+        }
+    };
+
+    match string("method")?.unwrap_or("internal") {
+        "internal" => Ok(Clipboard::Internal),
+        "osc52" => Ok(Clipboard::Osc52),
+        "auto" => Ok(Clipboard::detect()),
+        "command" => Ok(Clipboard::Command {
+            copy: command("copy")?.ok_or_else(|| {
+                anyhow::anyhow!("`clipboard.copy`: needed when `method` is `command`")
+            })?,
+            paste: command("paste")?,
+        }),
+        method => anyhow::bail!(
+            "`clipboard.method`: unknown method `{}` (expected `internal`, `osc52`, `command` or \
+             `auto`)",
+            method
+        ),
+    }
+}
+
+fn as_table<'a>(value: &'a Value, key: &str) -> anyhow::Result<&'a Table> {
+    value
+        .as_table()
+        .ok_or_else(|| anyhow::anyhow!("`{}`: expected a table", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::keymap::{Action, Key, Lookup},
+    };
+
+    fn lookup(config: &Config, key: &str) -> Lookup {
+        config.keymap.lookup(&[key.parse::<Key>().unwrap()])
+    }
+
+    #[test]
+    fn empty_config_is_the_default() {
+        let config = Config::parse("").unwrap();
+
+        assert!(!config.is_modal);
+        assert_eq!(lookup(&config, "C-s"), Lookup::Action(Action::Save));
+    }
+
+    #[test]
+    fn keys_are_bound_on_top_of_the_defaults() {
+        let config = Config::parse(
+            r#"
+            modal = true
+
+            [keys]
+            "C-w" = "save"
+            "C-s" = "none"
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.is_modal);
+        assert_eq!(lookup(&config, "C-w"), Lookup::Action(Action::Save));
+        assert_eq!(lookup(&config, "C-s"), Lookup::Unbound);
+        assert_eq!(lookup(&config, "C-z"), Lookup::Action(Action::Undo));
+    }
+
+    #[test]
+    fn errors_say_which_key_is_wrong() {
+        let error = |source| format!("{:#}", Config::parse(source).unwrap_err());
+
+        assert_eq!(error("modal = 1"), "`modal`: expected `true` or `false`");
+        assert_eq!(
+            error("tabs = 4"),
+            "`tabs`: unknown key (expected `modal`, `keys` or `clipboard`)"
+        );
+        assert_eq!(
+            error("[keys]\n\"C-w\" = 1"),
+            "`keys.C-w`: expected the name of an action"
+        );
+        assert_eq!(
+            error("[keys]\n\"C-w\" = \"frobnicate\""),
+            "`keys.C-w`: unknown action `frobnicate`"
+        );
+        assert_eq!(
+            error("[keys]\n\"X-w\" = \"save\""),
+            "`keys.X-w`: unknown modifier `X-` in `X-w`"
+        );
+    }
+
+    #[test]
+    fn overlapping_keys_are_an_error() {
+        let error = |source| format!("{:#}", Config::parse(source).unwrap_err());
+
+        assert_eq!(
+            error("[keys]\n\"C-x\" = \"save\"\n\"C-x C-s\" = \"undo\""),
+            "`keys.C-x C-s`: conflicts with `keys.C-x`"
+        );
+        assert_eq!(
+            error("[keys]\n\"C-x C-s\" = \"save\"\n\"C-x  C-s\" = \"none\""),
+            "`keys.C-x C-s`: conflicts with `keys.C-x  C-s`"
+        );
+
+        // Overriding a default binding is fine, as are chords that only share their first keys.
+        let config = Config::parse(
+            r#"
+            [keys]
+            "C-s" = "none"
+            "C-w s" = "save"
+            "C-w v" = "undo"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(lookup(&config, "C-w"), Lookup::Prefix);
+    }
+
+    #[test]
+    fn clipboard_commands_are_split_into_arguments() {
+        let config = Config::parse(
+            r#"
+            [clipboard]
+            method = "command"
+            copy = "xclip -selection clipboard"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.clipboard,
+            Clipboard::Command {
+                copy: vec!["xclip".into(), "-selection".into(), "clipboard".into()],
+                paste: None,
+            }
+        );
+        assert_eq!(
+            Config::parse("[clipboard]\nmethod = \"osc52\"")
+                .unwrap()
+                .clipboard,
+            Clipboard::Osc52
+        );
+    }
+
+    #[test]
+    fn clipboard_errors_say_which_key_is_wrong() {
+        let error = |source| format!("{:#}", Config::parse(source).unwrap_err());
+
+        assert_eq!(
+            error("[clipboard]\nmethod = \"command\""),
+            "`clipboard.copy`: needed when `method` is `command`"
+        );
+        assert_eq!(
+            error("[clipboard]\nmethod = \"command\"\ncopy = \" \""),
+            "`clipboard.copy`: empty command"
+        );
+        assert_eq!(
+            error("[clipboard]\nmethod = 52"),
+            "`clipboard.method`: expected a string"
+        );
+        assert_eq!(
+            error("[clipboard]\ncut = \"x\""),
+            "`clipboard.cut`: unknown key (expected `method`, `copy` or `paste`)"
+        );
+        assert_eq!(
+            error("[clipboard]\nmethod = \"xclip\""),
+            "`clipboard.method`: unknown method `xclip` (expected `internal`, `osc52`, `command` \
+             or `auto`)"
+        );
+    }
+}
@@ -0,0 +1,1412 @@
+use {
+    crate::{
+        buffer::{self, Buffer, Direction},
+        command::{self, Command, Setting},
+        config::Config,
+        graphemes,
+        keymap::{self, Action, Key, Keymap, Lookup},
+        layout::{Layout, Rect, SplitDirection},
+        prompt::{Prompt, PromptEvent},
+        register::Register,
+        replace::Replace,
+        search::{self, SearchDirection},
+        themes::{Theme, Themes},
+        vi::{self, Vi, ViEvent},
+        view::{LineNumbers, View},
+    },
+    crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind},
+    regex::{Regex, RegexBuilder},
+    std::{
+        convert::TryInto,
+        io::{self, Write},
+        ops::Range,
+        path::{Path, PathBuf},
+        time::{Duration, Instant},
+    },
+};
+
+// How close together two clicks in the same place have to be to count as a double-click.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(500);
+
+// How many lines the mouse wheel scrolls by.
+const SCROLL_LINES: isize = 3;
+
+// The editor as a whole: all the buffers the user has open, the views onto them that divide up the
+// screen, and everything that is shared between them.
+#[derive(Debug)]
+pub struct Editor {
+    buffers: Vec<Buffer>,
+    layout: Layout,
+    focused_view: usize,
+    themes: Themes,
+    theme: Theme,
+    line_numbers: LineNumbers,
+    keymap: Keymap,
+    // The keys of a chord typed so far, while waiting for the rest of it.
+    pending_keys: Vec<Key>,
+    // Modal editing is optional, and keeps track of which mode it is in.
+    vi: Option<Vi>,
+    register: Register,
+    // The last thing searched for, whose matches are highlighted until the user presses escape.
+    search_query: Option<String>,
+    // A find-and-replace waiting for the user to say what to do with the current match.
+    replace: Option<Replace>,
+    message: Option<String>,
+    // Whether the list of key bindings is shown. Like messages, it goes away on the next key.
+    is_showing_bindings: bool,
+    prompt: Option<(PromptPurpose, Prompt)>,
+    // When and where the mouse was last clicked, to tell when a click is the second of a
+    // double-click.
+    last_click: Option<(Instant, u16, u16)>,
+    // Whether the mouse button was pressed on some text, so that dragging selects.
+    is_dragging: bool,
+    // Whether the last key tried to quit with unsaved changes, so that quitting again straight
+    // away goes ahead anyway.
+    is_quitting: bool,
+    is_running: bool,
+}
+
+// What the input to a prompt is going to be used for.
+#[derive(Debug)]
+enum PromptPurpose {
+    Command,
+    Open,
+    SwitchBuffer,
+    // Searching moves the cursor as the query is typed, so we need to know where it started in
+    // case the search is cancelled, along with the query it replaces.
+    Search {
+        origin: (usize, usize),
+        previous_query: Option<String>,
+    },
+    // Find-and-replace asks for the regular expression to find first, and then what to replace
+    // its matches with.
+    ReplacePattern,
+    ReplaceWith(Regex),
+}
+
+impl Editor {
+    pub fn new(
+        paths: &[PathBuf],
+        theme_name: &str,
+        line_numbers: LineNumbers,
+    ) -> anyhow::Result<Self> {
+        let config = Config::load()?;
+        let themes = Themes::load()?;
+        let theme = themes.get(theme_name)?;
+
+        // Themes that failed to load only matter if they were asked for, so otherwise they are
+        // just reported.
+        let theme_errors = themes.errors();
+
+        let mut editor = Self::with_config(config, themes, theme, line_numbers);
+
+        for path in paths {
+            editor.load(path)?;
+        }
+
+        if let Some(errors) = theme_errors {
+            editor.message = Some(format!("Error: {}", errors));
+        }
+
+        Ok(editor)
+    }
+
+    // An editor with no buffers yet, which have to be loaded before it is used.
+    fn with_config(
+        config: Config,
+        themes: Themes,
+        theme: Theme,
+        line_numbers: LineNumbers,
+    ) -> Self {
+        // Start off with a single view on the first file the user gave us.
+        Self {
+            buffers: Vec::new(),
+            layout: Layout::View(View::new(0)),
+            focused_view: 0,
+            themes,
+            theme,
+            line_numbers,
+            keymap: config.keymap,
+            pending_keys: Vec::new(),
+            vi: if config.is_modal {
+                Some(Vi::default())
+            } else {
+                None
+            },
+            register: Register::new(config.clipboard),
+            search_query: None,
+            replace: None,
+            message: None,
+            is_showing_bindings: false,
+            prompt: None,
+            last_click: None,
+            is_dragging: false,
+            is_quitting: false,
+            is_running: true,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.is_running
+    }
+
+    // Pasted text arrives all at once (the terminal marks it as pasted when bracketed paste is
+    // enabled), rather than as keys. It is inserted in one go, so that it is undone all at once and
+    // isn’t interpreted as commands along the way, even in Vi’s normal mode. Prompts are single
+    // lines, so they get the text as if it was typed, minus any line breaks.
+    pub fn handle_paste(&mut self, text: &str) {
+        self.message = None;
+        self.is_showing_bindings = false;
+        self.is_quitting = false;
+
+        // Terminals send a carriage return for each line break.
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+
+        if self.replace.is_some() {
+            return;
+        }
+
+        if self.prompt.is_some() {
+            for c in text.chars().filter(|c| !c.is_control()) {
+                self.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+            }
+
+            return;
+        }
+
+        self.pending_keys.clear();
+
+        if let Some(vi) = &mut self.vi {
+            vi.deselect();
+        }
+
+        let buffer = &mut self.buffers[self.layout.view(self.focused_view).buffer_idx];
+
+        replacing_selection(buffer, |buffer| {
+            let at = buffer.cursor_byte_idx();
+            buffer.replace(at..at, &text);
+        });
+
+        if let Some(vi) = &self.vi {
+            vi.clamp_cursor(buffer);
+        }
+
+        let view = self.layout.view_mut(self.focused_view);
+        view.save_cursor(&self.buffers[view.buffer_idx]);
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        // Messages only stay up until the next key press, and so does the chance to confirm
+        // quitting.
+        self.message = None;
+        self.is_showing_bindings = false;
+        let was_quitting = self.is_quitting;
+
+        // While a find-and-replace or a prompt is going on it gets all the keys.
+        if self.replace.is_some() {
+            self.handle_replace_key(key);
+        } else if let Some((purpose, prompt)) = &mut self.prompt {
+            match prompt.handle_key(key) {
+                PromptEvent::Submitted(input) => {
+                    let (purpose, _) = self.prompt.take().unwrap();
+                    self.submit_prompt(purpose, &input);
+                }
+                PromptEvent::Cancelled => {
+                    let (purpose, _) = self.prompt.take().unwrap();
+                    self.cancel_prompt(purpose);
+                }
+                PromptEvent::Edited => {
+                    if let PromptPurpose::Search { origin, .. } = purpose {
+                        let (origin, query) = (*origin, prompt.input().to_string());
+                        self.search_incrementally(origin, &query);
+                    }
+                }
+                PromptEvent::CompletionRequested => {
+                    let completions = match purpose {
+                        PromptPurpose::Command => {
+                            let theme_names: Vec<_> = self.themes.names().collect();
+                            command::complete(prompt.input(), &theme_names)
+                        }
+                        PromptPurpose::Open => command::complete_path(prompt.input()),
+                        _ => Vec::new(),
+                    };
+
+                    prompt.set_completions(completions);
+                }
+                PromptEvent::Ignored => {}
+            }
+        } else {
+            self.handle_modal_key(key);
+        }
+
+        if was_quitting {
+            self.is_quitting = false;
+        }
+
+        if let Some(error) = self.register.take_error() {
+            self.message = Some(error);
+        }
+
+        // Whatever the key did, the focused view has to follow the cursor.
+        let view = self.layout.view_mut(self.focused_view);
+        view.save_cursor(&self.buffers[view.buffer_idx]);
+    }
+
+    // Clicking moves the cursor (focusing the view it is in), dragging or shift-clicking selects,
+    // and double-clicking selects a word. The wheel scrolls whichever view the mouse is over
+    // without moving the cursor, until the next key brings the cursor back into view.
+    pub fn handle_mouse(&mut self, event: MouseEvent) {
+        // The mouse can’t do anything useful while a prompt or find-and-replace is waiting for
+        // input, other than scrolling.
+        let is_busy = self.replace.is_some() || self.prompt.is_some();
+
+        let MouseEvent {
+            kind,
+            column: x,
+            row: y,
+            modifiers,
+        } = event;
+
+        match kind {
+            MouseEventKind::Down(MouseButton::Left) if !is_busy => {
+                self.message = None;
+                self.is_showing_bindings = false;
+                self.is_quitting = false;
+                self.pending_keys.clear();
+
+                let view_idx = match self.view_at(x, y) {
+                    Some(view_idx) => view_idx,
+                    None => return,
+                };
+
+                if view_idx != self.focused_view {
+                    self.focus(view_idx);
+                }
+
+                // Clicking a status line only focuses its view.
+                if self.layout.view(view_idx).is_on_status_line(y) {
+                    return;
+                }
+
+                let now = Instant::now();
+                let is_double_click = matches!(self.last_click, Some((time, last_x, last_y))
+                    if now - time < DOUBLE_CLICK_TIME && (last_x, last_y) == (x, y));
+
+                // A third click starts over rather than being another double-click.
+                self.last_click = if is_double_click {
+                    None
+                } else {
+                    Some((now, x, y))
+                };
+
+                if is_double_click {
+                    self.select_word(x, y);
+                } else if modifiers.contains(KeyModifiers::SHIFT) {
+                    self.extend_selection(x, y);
+                } else {
+                    self.deselect();
+                    self.move_cursor_to_mouse(x, y);
+                }
+
+                self.is_dragging = true;
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.is_dragging && !is_busy => {
+                self.extend_selection(x, y);
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.is_dragging = false,
+            MouseEventKind::ScrollUp => self.scroll(x, y, -SCROLL_LINES),
+            MouseEventKind::ScrollDown => self.scroll(x, y, SCROLL_LINES),
+            _ => {}
+        }
+    }
+
+    fn view_at(&self, x: u16, y: u16) -> Option<usize> {
+        self.layout
+            .views()
+            .iter()
+            .position(|view| view.contains(x, y))
+    }
+
+    fn scroll(&mut self, x: u16, y: u16, lines: isize) {
+        let view_idx = self.view_at(x, y).unwrap_or(self.focused_view);
+        let view = self.layout.view_mut(view_idx);
+        view.scroll(lines, &self.buffers[view.buffer_idx]);
+    }
+
+    fn move_cursor_to_mouse(&mut self, x: u16, y: u16) {
+        let view = self.layout.view(self.focused_view);
+        let buffer = &mut self.buffers[view.buffer_idx];
+        let (line_nr, col_nr) = view.position_at(x, y, buffer);
+
+        buffer.move_cursor_to(line_nr, col_nr);
+
+        if let Some(vi) = &self.vi {
+            vi.clamp_cursor(buffer);
+        }
+
+        let view = self.layout.view_mut(self.focused_view);
+        view.save_cursor(&self.buffers[view.buffer_idx]);
+    }
+
+    // Selects from wherever the cursor is (unless something is already selected) to the mouse.
+    fn extend_selection(&mut self, x: u16, y: u16) {
+        let buffer = &mut self.buffers[self.layout.view(self.focused_view).buffer_idx];
+
+        match &mut self.vi {
+            Some(vi) if !vi.is_selecting() => vi.select(buffer),
+            Some(_) => {}
+            None => buffer.start_selection(),
+        }
+
+        self.move_cursor_to_mouse(x, y);
+    }
+
+    fn select_word(&mut self, x: u16, y: u16) {
+        self.deselect();
+        self.move_cursor_to_mouse(x, y);
+
+        let buffer = &mut self.buffers[self.layout.view(self.focused_view).buffer_idx];
+        let word = vi::word_at(buffer, buffer.cursor_byte_idx());
+
+        if word.is_empty() {
+            return;
+        }
+
+        buffer.move_cursor_to_byte(word.start);
+
+        // Vi’s selection includes the character under the cursor, so the cursor goes on the last
+        // character of the word rather than after it.
+        match &mut self.vi {
+            Some(vi) => {
+                vi.select(buffer);
+                let last_char = buffer.char_before(word.end).unwrap();
+                buffer.move_cursor_to_byte(word.end - last_char.len_utf8());
+            }
+            None => {
+                buffer.start_selection();
+                buffer.move_cursor_to_byte(word.end);
+            }
+        }
+
+        let view = self.layout.view_mut(self.focused_view);
+        view.save_cursor(&self.buffers[view.buffer_idx]);
+    }
+
+    fn deselect(&mut self) {
+        self.focused_buffer().clear_selection();
+
+        if let Some(vi) = &mut self.vi {
+            vi.deselect();
+        }
+    }
+
+    // With modal editing, keys go to Vi first, unless they finish off a chord that has already
+    // been started.
+    fn handle_modal_key(&mut self, key: KeyEvent) {
+        let vi = match &mut self.vi {
+            Some(vi) if self.pending_keys.is_empty() => vi,
+            _ => return self.handle_editing_key(key),
+        };
+
+        let buffer = &mut self.buffers[self.layout.view(self.focused_view).buffer_idx];
+
+        match vi.handle_key(key, buffer, &mut self.register) {
+            ViEvent::Handled => return,
+            ViEvent::Action(action) => self.run_action(action),
+            ViEvent::Unhandled => self.handle_editing_key(key),
+        }
+
+        let buffer = &mut self.buffers[self.layout.view(self.focused_view).buffer_idx];
+
+        if let Some(vi) = &self.vi {
+            vi.clamp_cursor(buffer);
+        }
+    }
+
+    // Keys are looked up in the keymap, waiting for more keys if they might be the start of a
+    // chord. Unbound characters are typed into the buffer.
+    fn handle_editing_key(&mut self, key: KeyEvent) {
+        self.pending_keys.push(Key::from(key));
+
+        match self.keymap.lookup(&self.pending_keys) {
+            Lookup::Action(action) => {
+                self.pending_keys.clear();
+                self.run_action(action);
+            }
+            Lookup::Prefix => self.message = Some(keymap::pending_message(&self.pending_keys)),
+            Lookup::Unbound => {
+                let keys = std::mem::take(&mut self.pending_keys);
+
+                // Capital letters arrive with the shift modifier set.
+                match (key.code, key.modifiers) {
+                    (KeyCode::Char(c), KeyModifiers::NONE)
+                    | (KeyCode::Char(c), KeyModifiers::SHIFT)
+                        if keys.len() == 1 =>
+                    {
+                        // Typing replaces whatever is selected.
+                        replacing_selection(self.focused_buffer(), |buffer| buffer.insert_char(c))
+                    }
+                    _ => self.message = Some(keymap::unbound_message(&keys)),
+                }
+            }
+        }
+    }
+
+    fn run_action(&mut self, action: Action) {
+        let buffer = &mut self.buffers[self.layout.view(self.focused_view).buffer_idx];
+
+        // Moving the cursor without selecting deselects whatever was selected.
+        match action {
+            Action::MoveUp
+            | Action::MoveDown
+            | Action::MoveLeft
+            | Action::MoveRight
+            | Action::MoveLineStart
+            | Action::MoveLineEnd
+            | Action::MoveBufferStart
+            | Action::MoveBufferEnd
+            | Action::PageUp
+            | Action::PageDown => buffer.clear_selection(),
+            Action::SelectUp
+            | Action::SelectDown
+            | Action::SelectLeft
+            | Action::SelectRight
+            | Action::SelectLineStart
+            | Action::SelectLineEnd
+            | Action::SelectBufferStart
+            | Action::SelectBufferEnd
+            | Action::SelectPageUp
+            | Action::SelectPageDown => buffer.start_selection(),
+            _ => {}
+        }
+
+        match action {
+            Action::MoveUp | Action::SelectUp => buffer.move_cursor(Direction::Up),
+            Action::MoveDown | Action::SelectDown => buffer.move_cursor(Direction::Down),
+            Action::MoveLeft | Action::SelectLeft => buffer.move_cursor(Direction::Left),
+            Action::MoveRight | Action::SelectRight => buffer.move_cursor(Direction::Right),
+            Action::MoveLineStart | Action::SelectLineStart => {
+                buffer.move_cursor(Direction::LineStart)
+            }
+            Action::MoveLineEnd | Action::SelectLineEnd => buffer.move_cursor(Direction::LineEnd),
+            Action::MoveBufferStart | Action::SelectBufferStart => {
+                buffer.move_cursor(Direction::BufferStart)
+            }
+            Action::MoveBufferEnd | Action::SelectBufferEnd => {
+                buffer.move_cursor(Direction::BufferEnd)
+            }
+            Action::PageUp | Action::SelectPageUp => self.page(-1),
+            Action::PageDown | Action::SelectPageDown => self.page(1),
+            // Backspace deletes the selection if there is one, rather than the character before
+            // the cursor.
+            Action::Backspace => {
+                if !buffer.delete_selection() {
+                    buffer.backspace()
+                }
+            }
+            Action::Newline => replacing_selection(buffer, Buffer::insert_newline),
+            Action::Cut | Action::Copy => {
+                if let Some(text) = buffer.selected_text() {
+                    self.register.set(text, false);
+
+                    if action == Action::Cut {
+                        buffer.delete_selection();
+                    } else {
+                        buffer.clear_selection();
+                    }
+                } else {
+                    self.message = Some("Nothing is selected".to_string());
+                }
+            }
+            Action::Paste => paste(buffer, &mut self.register),
+            Action::Save => self.save(),
+            Action::Undo => buffer.undo(),
+            Action::Redo => buffer.redo(),
+            // Cycling through themes lets the user try them out while editing.
+            Action::CycleTheme => self.cycle_theme(),
+            Action::CommandLine => self.prompt = Some((PromptPurpose::Command, Prompt::new(": "))),
+            Action::Open => self.prompt = Some((PromptPurpose::Open, Prompt::new("Open: "))),
+            // The prompt lists the open buffers to pick from.
+            Action::SwitchBuffer => {
+                let label = format!("Switch to buffer ({}): ", self.buffer_list());
+                self.prompt = Some((PromptPurpose::SwitchBuffer, Prompt::new(label)));
+            }
+            Action::Search => {
+                let purpose = PromptPurpose::Search {
+                    origin: (buffer.line_nr(), buffer.col_nr()),
+                    previous_query: self.search_query.take(),
+                };
+                self.prompt = Some((purpose, Prompt::new("Search: ")));
+            }
+            Action::NextMatch => self.repeat_search(SearchDirection::Forward),
+            Action::PreviousMatch => self.repeat_search(SearchDirection::Backward),
+            Action::ClearSearch => self.search_query = None,
+            Action::Replace => self.prompt_for_replace(),
+            Action::SplitHorizontal => self.split(SplitDirection::Horizontal),
+            Action::SplitVertical => self.split(SplitDirection::Vertical),
+            Action::FocusNextView => self.focus((self.focused_view + 1) % self.layout.len()),
+            Action::CloseView => self.close_view(),
+            Action::ListBindings => self.is_showing_bindings = true,
+            Action::Quit => self.quit(),
+        }
+    }
+
+    fn submit_prompt(&mut self, purpose: PromptPurpose, input: &str) {
+        // Submitting an empty prompt is the same as cancelling it, except when giving the
+        // replacement for a find-and-replace, which can be empty to delete the matches.
+        if input.is_empty() && !matches!(purpose, PromptPurpose::ReplaceWith(_)) {
+            self.cancel_prompt(purpose);
+            return;
+        }
+
+        let result = match purpose {
+            PromptPurpose::Command => command::parse(input).and_then(|c| self.run_command(c)),
+            PromptPurpose::Open => self.open(Path::new(input)),
+            PromptPurpose::SwitchBuffer => self.switch_to_buffer(input),
+            // The cursor is already on the first match, since the search happens as the query is
+            // typed.
+            PromptPurpose::Search { origin, .. } => {
+                self.search_incrementally(origin, input);
+                self.search_query = Some(input.to_string());
+                Ok(())
+            }
+            PromptPurpose::ReplacePattern => parse_regex(input).map(|regex| {
+                let label = format!("Replace `{}` with: ", regex);
+                self.prompt = Some((PromptPurpose::ReplaceWith(regex), Prompt::new(label)));
+            }),
+            PromptPurpose::ReplaceWith(regex) => {
+                self.start_replace(regex, input.to_string());
+                Ok(())
+            }
+        };
+
+        if let Err(e) = result {
+            self.message = Some(format!("Error: {:#}", e));
+        }
+    }
+
+    fn run_command(&mut self, command: Command) -> anyhow::Result<()> {
+        match command {
+            Command::Open(path) => self.open(&path)?,
+            Command::Write => self.save(),
+            Command::Quit { is_forced } => match self.unsaved_buffers() {
+                Some(paths) if !is_forced => {
+                    anyhow::bail!("{} not saved (use `quit!` to discard the changes)", paths)
+                }
+                _ => self.is_running = false,
+            },
+            Command::Goto(line_nr) => self.focused_buffer().move_cursor_to(line_nr, 0),
+            Command::Theme(name) => {
+                let theme = self.themes.get(&name)?;
+                self.set_theme(theme);
+            }
+            Command::Set(Setting::LineNumbers(line_numbers)) => self.line_numbers = line_numbers,
+            Command::Keys => self.is_showing_bindings = true,
+            // Without this there would be no way to find-and-replace with modal editing, where
+            // C-r redoes.
+            Command::Replace => self.prompt_for_replace(),
+        }
+
+        Ok(())
+    }
+
+    fn cancel_prompt(&mut self, purpose: PromptPurpose) {
+        if let PromptPurpose::Search {
+            origin,
+            previous_query,
+        } = purpose
+        {
+            self.focused_buffer().move_cursor_to(origin.0, origin.1);
+            self.search_query = previous_query;
+        }
+    }
+
+    // Every change to the query searches again from where the cursor was before the search
+    // started, so that deleting characters from the query moves the cursor back again.
+    fn search_incrementally(&mut self, origin: (usize, usize), query: &str) {
+        let buffer = self.focused_buffer();
+        buffer.move_cursor_to(origin.0, origin.1);
+
+        self.message = match buffer.find(query, SearchDirection::Forward, true) {
+            Some(true) => Some("Search wrapped".to_string()),
+            Some(false) => None,
+            None => Some(format!("No matches for `{}`", query)),
+        };
+    }
+
+    // Moves to the next or previous match of the last search, skipping the one under the cursor.
+    fn repeat_search(&mut self, direction: SearchDirection) {
+        let query = match self.search_query.clone() {
+            Some(query) => query,
+            None => {
+                self.message = Some("Nothing has been searched for yet".to_string());
+                return;
+            }
+        };
+
+        self.message = match self.focused_buffer().find(&query, direction, false) {
+            Some(true) => Some("Search wrapped".to_string()),
+            Some(false) => None,
+            None => Some(format!("No matches for `{}`", query)),
+        };
+    }
+
+    fn prompt_for_replace(&mut self) {
+        let label = if self.selection().is_some() {
+            "Replace in selection: "
+        } else {
+            "Replace: "
+        };
+        self.prompt = Some((PromptPurpose::ReplacePattern, Prompt::new(label)));
+    }
+
+    // What is selected in the focused buffer, either in Vi’s visual mode or otherwise.
+    fn selection(&self) -> Option<Range<usize>> {
+        let buffer = &self.buffers[self.layout.view(self.focused_view).buffer_idx];
+
+        match &self.vi {
+            Some(vi) if vi.is_selecting() => vi.selection(buffer),
+            _ => buffer.selection(),
+        }
+    }
+
+    // Find-and-replace works on the selection if there is one, and otherwise on the whole of the
+    // buffer, going through the matches from the top.
+    fn start_replace(&mut self, regex: Regex, replacement: String) {
+        let region = self.selection();
+        self.deselect();
+
+        let buffer = self.focused_buffer();
+        let region = region.unwrap_or(0..buffer.len_bytes());
+
+        self.replace = Some(Replace::new(regex, replacement, region, buffer));
+        self.finish_replace_if_done();
+    }
+
+    // Like Emacs’ `query-replace`: y or space replaces the current match, n or backspace skips
+    // it, a or ! replaces it and every match after it, and q, escape or enter stop.
+    fn handle_replace_key(&mut self, key: KeyEvent) {
+        let buffer = &mut self.buffers[self.layout.view(self.focused_view).buffer_idx];
+        let replace = self.replace.as_mut().unwrap();
+
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char(' ') => replace.replace_current(buffer),
+            KeyCode::Char('n') | KeyCode::Backspace => replace.skip_current(buffer),
+            KeyCode::Char('a') | KeyCode::Char('!') => replace.replace_all(buffer),
+            KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter => {
+                let count = self.replace.take().unwrap().finish(buffer);
+                self.message = Some(replaced_message(count));
+            }
+            _ => {}
+        }
+
+        self.finish_replace_if_done();
+    }
+
+    fn finish_replace_if_done(&mut self) {
+        if self
+            .replace
+            .as_ref()
+            .is_some_and(|replace| replace.has_match())
+        {
+            return;
+        }
+
+        if let Some(replace) = self.replace.take() {
+            let regex = replace.regex().to_string();
+            let count = replace.finish(self.focused_buffer());
+
+            self.message = Some(if count == 0 {
+                format!("No matches for `{}`", regex)
+            } else {
+                replaced_message(count)
+            });
+        }
+    }
+
+    fn focused_buffer(&mut self) -> &mut Buffer {
+        &mut self.buffers[self.layout.view(self.focused_view).buffer_idx]
+    }
+
+    // Opens the file at the given path and shows it in the focused view.
+    fn open(&mut self, path: &Path) -> anyhow::Result<()> {
+        let buffer_idx = self.load(path)?;
+        self.show_buffer(buffer_idx);
+
+        Ok(())
+    }
+
+    // Loads the file at the given path into a new buffer, returning its index. If the file is
+    // already open then we just use its existing buffer instead.
+    fn load(&mut self, path: &Path) -> anyhow::Result<usize> {
+        if let Some(idx) = self
+            .buffers
+            .iter()
+            .position(|buffer| is_same_file(buffer.path(), path))
+        {
+            return Ok(idx);
+        }
+
+        let buffer = Buffer::new(path)?;
+
+        if !path.exists() {
+            self.message = Some(buffer::new_file_message(path));
+        }
+
+        self.buffers.push(buffer);
+
+        Ok(self.buffers.len() - 1)
+    }
+
+    fn show_buffer(&mut self, buffer_idx: usize) {
+        self.leave_focused_buffer();
+        self.layout
+            .view_mut(self.focused_view)
+            .show(buffer_idx, &self.buffers[buffer_idx]);
+    }
+
+    // Buffers can be picked either by their number in the buffer list, or by (part of) their path.
+    fn switch_to_buffer(&mut self, query: &str) -> anyhow::Result<()> {
+        if let Ok(nr) = query.parse::<usize>() {
+            if nr == 0 || nr > self.buffers.len() {
+                anyhow::bail!("there is no buffer {}", nr);
+            }
+
+            self.show_buffer(nr - 1);
+            return Ok(());
+        }
+
+        let matches: Vec<_> = self
+            .buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| buffer.path().to_string_lossy().contains(query))
+            .map(|(idx, _)| idx)
+            .collect();
+
+        match matches.as_slice() {
+            [idx] => {
+                self.show_buffer(*idx);
+                Ok(())
+            }
+            [] => anyhow::bail!("no buffer matches `{}`", query),
+            _ => anyhow::bail!("more than one buffer matches `{}`", query),
+        }
+    }
+
+    // The list of open buffers, numbered as they can be picked by. The buffer in the focused view
+    // is surrounded in brackets.
+    fn buffer_list(&self) -> String {
+        let current_buffer = self.layout.view(self.focused_view).buffer_idx;
+
+        self.buffers
+            .iter()
+            .enumerate()
+            .map(|(idx, buffer)| {
+                let entry = format!("{}: {}", idx + 1, buffer.path().display());
+
+                if idx == current_buffer {
+                    format!("[{}]", entry)
+                } else {
+                    entry
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    // A failed save is reported rather than propagated, since quitting would throw away the user’s
+    // edits.
+    fn save(&mut self) {
+        let buffer = &mut self.buffers[self.layout.view(self.focused_view).buffer_idx];
+
+        self.message = Some(match buffer.save() {
+            Ok(()) => format!(
+                "Wrote {} lines to {}",
+                buffer.len_lines(),
+                buffer.path().display()
+            ),
+            Err(e) => format!("Error: {:#}", e),
+        });
+    }
+
+    // Quitting with unsaved changes only goes ahead if the user quits again straight away.
+    fn quit(&mut self) {
+        match self.unsaved_buffers() {
+            Some(paths) if !self.is_quitting => {
+                self.message = Some(format!(
+                    "{} not saved (quit again to discard the changes)",
+                    paths
+                ));
+                self.is_quitting = true;
+            }
+            _ => self.is_running = false,
+        }
+    }
+
+    // The paths of the buffers with unsaved changes, if there are any.
+    fn unsaved_buffers(&self) -> Option<String> {
+        let paths: Vec<_> = self
+            .buffers
+            .iter()
+            .filter(|buffer| buffer.is_modified())
+            .map(|buffer| buffer.path().display().to_string())
+            .collect();
+
+        if paths.is_empty() {
+            None
+        } else {
+            Some(paths.join(", "))
+        }
+    }
+
+    // The new view shows the same buffer as the focused one, and gets focus itself.
+    fn split(&mut self, direction: SplitDirection) {
+        self.layout.split(self.focused_view, direction);
+        self.focus(self.focused_view + 1);
+    }
+
+    // Paging moves the cursor and the view together by the height of the view, so that the cursor
+    // stays on the same line of the screen until it reaches the start or end of the buffer.
+    // Negative numbers of pages go up.
+    fn page(&mut self, pages: isize) {
+        let view = self.layout.view_mut(self.focused_view);
+        let buffer = &mut self.buffers[view.buffer_idx];
+        let lines = pages * view.window_lines() as isize;
+
+        buffer.move_cursor_to(
+            buffer.line_nr().saturating_add_signed(lines),
+            buffer.col_nr(),
+        );
+        view.scroll(lines, buffer);
+    }
+
+    // The view that takes focus puts its own cursor back into its buffer, since the buffer’s
+    // cursor might have been moved through another view since.
+    fn focus(&mut self, view_idx: usize) {
+        self.leave_focused_buffer();
+        self.focused_view = view_idx;
+
+        let view = self.layout.view_mut(view_idx);
+        view.restore_cursor(&mut self.buffers[view.buffer_idx]);
+    }
+
+    // Called before the focused view stops showing its buffer, or loses focus.
+    fn leave_focused_buffer(&mut self) {
+        let buffer = &mut self.buffers[self.layout.view(self.focused_view).buffer_idx];
+
+        if let Some(vi) = &mut self.vi {
+            vi.return_to_normal(buffer);
+        }
+    }
+
+    fn close_view(&mut self) {
+        if self.layout.len() == 1 {
+            self.message = Some("Can’t close the only view".to_string());
+            return;
+        }
+
+        self.leave_focused_buffer();
+
+        let view = self.layout.view_mut(self.focused_view);
+        view.save_cursor(&self.buffers[view.buffer_idx]);
+
+        let layout = std::mem::replace(&mut self.layout, Layout::View(View::new(0)));
+        self.layout = layout.close(self.focused_view).unwrap();
+
+        // Focus moves to the view after the closed one, or the one before if it was the last. The
+        // closed view has already been left, so this doesn’t go through `focus`, which would leave
+        // whichever view now has its index.
+        self.focused_view = self.focused_view.min(self.layout.len() - 1);
+
+        let view = self.layout.view_mut(self.focused_view);
+        view.restore_cursor(&mut self.buffers[view.buffer_idx]);
+    }
+
+    fn cycle_theme(&mut self) {
+        self.set_theme(self.themes.next(&self.theme));
+    }
+
+    // Buffers cache their highlighted lines, which are in the old theme’s colors.
+    fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+
+        for buffer in &mut self.buffers {
+            buffer.invalidate_highlights();
+        }
+
+        self.message = Some(format!("Theme: {}", self.theme.name));
+    }
+
+    // The views share the whole of the terminal apart from the message line at the bottom. Returns
+    // where the separators between views go.
+    fn arrange_views(&mut self, cols: u16, lines: u16) -> Vec<Rect> {
+        let (rects, separators) = self.layout.arrange(Rect {
+            x: 0,
+            y: 0,
+            cols,
+            lines: lines.saturating_sub(1),
+        });
+
+        for (view, rect) in self.layout.views_mut().into_iter().zip(rects) {
+            let buffer = &self.buffers[view.buffer_idx];
+
+            view.set_rect(rect, buffer);
+            view.set_line_numbers(self.line_numbers, buffer);
+        }
+
+        separators
+    }
+
+    // All this function does is cover the the terminal in the colors of the theme.
+    pub fn initialize_terminal(&self, stdout: &mut io::Stdout) -> anyhow::Result<()> {
+        use {
+            crossterm::{cursor, queue, terminal},
+            dialect::Theme,
+        };
+
+        let default_style: ansi_term::Style = self.theme.default_style().into();
+        let (_, lines) = terminal::size()?;
+
+        // Move the cursor to the top-left, change the colours to be that of the theme, and finally
+        // clear each line of the terminal.
+        queue!(stdout, cursor::MoveTo(0, 0))?;
+        stdout.write_all(default_style.prefix().to_string().as_bytes())?;
+
+        for _ in 0..lines {
+            queue!(stdout, terminal::Clear(terminal::ClearType::CurrentLine))?;
+            stdout.write_all(b"\r\n")?;
+        }
+
+        queue!(stdout, cursor::MoveTo(0, 0))?;
+
+        Ok(())
+    }
+
+    pub fn redraw(&mut self, stdout: &mut io::Stdout) -> anyhow::Result<()> {
+        use {
+            crossterm::{cursor, execute, queue, terminal},
+            dialect::Theme,
+        };
+
+        // Hiding the cursor makes redrawing less distracting.
+        execute!(stdout, cursor::Hide)?;
+
+        let (cols, lines) = terminal::size()?;
+        let message_line = lines.saturating_sub(1);
+        let separators = self.arrange_views(cols, lines);
+
+        // Start off in the theme’s default style, since the theme might have changed since the
+        // last redraw.
+        let default_style: ansi_term::Style = self.theme.default_style().into();
+        stdout.write_all(default_style.prefix().to_string().as_bytes())?;
+
+        let focused_buffer = &self.buffers[self.layout.view(self.focused_view).buffer_idx];
+        let selection = match &self.vi {
+            Some(vi) => vi.selection(focused_buffer),
+            None => None,
+        }
+        .or_else(|| focused_buffer.selection());
+
+        // Matches are highlighted as the user types what to search for.
+        let highlighted = match (&self.replace, &self.prompt) {
+            (Some(replace), _) => Some(replace.regex().clone()),
+            (None, Some((PromptPurpose::Search { .. }, prompt))) => {
+                search::literal_regex(prompt.input())
+            }
+            (None, Some((PromptPurpose::ReplacePattern, prompt))) => {
+                parse_regex(prompt.input()).ok()
+            }
+            (None, Some((PromptPurpose::ReplaceWith(regex), _))) => Some(regex.clone()),
+            _ => self.search_query.as_deref().and_then(search::literal_regex),
+        };
+
+        let focused_view = self.focused_view;
+
+        for (idx, view) in self.layout.views().into_iter().enumerate() {
+            view.draw(
+                stdout,
+                &mut self.buffers[view.buffer_idx],
+                &self.theme,
+                idx == focused_view,
+                highlighted.as_ref(),
+                selection.as_ref().filter(|_| idx == focused_view),
+            )?;
+        }
+
+        for separator in separators {
+            for line in separator.y..separator.y + separator.lines {
+                queue!(stdout, cursor::MoveTo(separator.x, line))?;
+                stdout.write_all("│".as_bytes())?;
+            }
+        }
+
+        if self.is_showing_bindings {
+            self.draw_bindings(stdout, cols, message_line)?;
+        }
+
+        // If a prompt is open then it takes the place of the message, and the cursor goes to the
+        // prompt.
+        queue!(
+            stdout,
+            cursor::MoveTo(0, message_line),
+            terminal::Clear(terminal::ClearType::UntilNewLine),
+        )?;
+
+        let cursor_position = if let Some((_, prompt)) = &self.prompt {
+            let (displayed, cursor_col) = prompt.display(usize::from(cols));
+            stdout.write_all(displayed.as_bytes())?;
+
+            Some((cursor_col.try_into().unwrap(), message_line))
+        } else {
+            let replace_question = self.replace.as_ref().map(|replace| {
+                format!(
+                    "Replace with `{}`? (y)es, (n)o, (a)ll, (q)uit",
+                    replace.replacement()
+                )
+            });
+
+            let mode_name = self
+                .vi
+                .as_ref()
+                .and_then(|vi| vi.mode_name())
+                .map(ToString::to_string);
+
+            if let Some(message) = replace_question
+                .as_ref()
+                .or(self.message.as_ref())
+                .or(mode_name.as_ref())
+            {
+                let message = graphemes::clip(message, 0, usize::from(cols));
+                stdout.write_all(message.as_bytes())?;
+            }
+
+            let view = self.layout.view(self.focused_view);
+            view.cursor_screen_position(&self.buffers[view.buffer_idx])
+        };
+
+        // Move the cursor to its position, and show it again so the user knows where it is. If
+        // it has been scrolled out of view then it stays hidden.
+        if let Some((x, y)) = cursor_position {
+            queue!(stdout, cursor::MoveTo(x, y), cursor::Show)?;
+        }
+
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    // The key bindings are listed in columns just above the message line, covering up the bottom of
+    // the views. If there are too many to fit then the rest are left out.
+    fn draw_bindings(&self, stdout: &mut io::Stdout, cols: u16, bottom: u16) -> anyhow::Result<()> {
+        use {
+            crossterm::{cursor, queue},
+            dialect::Theme,
+        };
+
+        let bindings = self.keymap.bindings();
+        let keys_width = bindings
+            .iter()
+            .map(|(keys, _)| graphemes::display_width(keys))
+            .max()
+            .unwrap_or(0);
+        let action_width = bindings
+            .iter()
+            .map(|(_, action)| action.len())
+            .max()
+            .unwrap_or(0);
+
+        let cols = usize::from(cols);
+        let column_width = keys_width + action_width + 5;
+        let columns = (cols / column_width).max(1);
+        let rows = bindings.len().div_ceil(columns).min(usize::from(bottom));
+
+        let default_style: ansi_term::Style = self.theme.default_style().into();
+        let style = self.theme.status_line_style(false);
+
+        for row in 0..rows {
+            let line: String = (0..columns)
+                .filter_map(|column| bindings.get(column * rows + row))
+                .map(|(keys, action)| {
+                    format!(
+                        "  {:<keys_width$}  {:<action_width$} ",
+                        keys,
+                        action,
+                        keys_width = keys_width,
+                        action_width = action_width
+                    )
+                })
+                .collect();
+            let line = graphemes::clip(&line, 0, cols);
+            let padding = cols - graphemes::display_width(&line);
+
+            queue!(stdout, cursor::MoveTo(0, bottom - rows as u16 + row as u16))?;
+            write!(
+                stdout,
+                "{}{}{}{}",
+                default_style.infix(style),
+                line,
+                " ".repeat(padding),
+                style.infix(default_style)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// `^` and `$` match at the start and end of every line, as they do in other editors.
+//
+// Syntax errors from the regex crate span several lines, pointing out where in the expression the
+// error is. Only the last line, which says what the error is, fits on the message line.
+fn parse_regex(input: &str) -> anyhow::Result<Regex> {
+    if input.is_empty() {
+        anyhow::bail!("empty regular expression");
+    }
+
+    RegexBuilder::new(input)
+        .multi_line(true)
+        .build()
+        .map_err(|e| {
+            let e = e.to_string();
+            let description = e.lines().last().unwrap_or_default();
+
+            anyhow::anyhow!(
+                "invalid regular expression: {}",
+                description.trim_start_matches("error: ")
+            )
+        })
+}
+
+// Makes an edit in place of the selection, if there is one, which can be undone along with it.
+fn replacing_selection(buffer: &mut Buffer, edit: impl FnOnce(&mut Buffer)) {
+    if buffer.selection().is_some() {
+        buffer.begin_edit_group();
+        buffer.delete_selection();
+        edit(buffer);
+        buffer.end_edit_group();
+    } else {
+        edit(buffer);
+    }
+}
+
+// Pasted text always goes in at the cursor, even text taken from whole lines, which only Vi’s `p`
+// and `P` put on lines of their own.
+fn paste(buffer: &mut Buffer, register: &mut Register) {
+    let text = register.text();
+
+    replacing_selection(buffer, |buffer| {
+        let at = buffer.cursor_byte_idx();
+        buffer.replace(at..at, &text);
+    });
+}
+
+fn replaced_message(count: usize) -> String {
+    format!(
+        "Replaced {} occurrence{}",
+        count,
+        if count == 1 { "" } else { "s" }
+    )
+}
+
+// Paths are compared after resolving them where possible, so that e.g. `./lib.rs` and `lib.rs` are
+// considered the same file.
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An editor on a single new buffer holding the given text, without reading the user’s config.
+    fn editor(contents: &str) -> Editor {
+        let themes = Themes::default();
+        let theme = themes.get("gruvbox").unwrap();
+        let mut editor = Editor::with_config(Config::default(), themes, theme, LineNumbers::Off);
+
+        editor.load(Path::new("test.txt")).unwrap();
+        let buffer = editor.focused_buffer();
+        buffer.replace(0..0, contents);
+        buffer.move_cursor_to(0, 0);
+
+        editor
+    }
+
+    fn press(editor: &mut Editor, code: KeyCode, modifiers: KeyModifiers) {
+        editor.handle_key(KeyEvent::new(code, modifiers));
+    }
+
+    fn type_chars(editor: &mut Editor, chars: &str) {
+        for c in chars.chars() {
+            press(editor, KeyCode::Char(c), KeyModifiers::NONE);
+        }
+    }
+
+    // What redraw highlights as selected.
+    fn selection(editor: &mut Editor) -> Option<Range<usize>> {
+        let buffer = &editor.buffers[editor.layout.view(editor.focused_view).buffer_idx];
+        editor.vi.as_ref().and_then(|vi| vi.selection(buffer))
+    }
+
+    #[test]
+    fn visual_selection_survives_undoing_the_text_under_it() {
+        let mut editor = editor("ééééé");
+        editor.vi = Some(Vi::default());
+
+        type_chars(&mut editor, "ix");
+        press(&mut editor, KeyCode::Esc, KeyModifiers::NONE);
+        type_chars(&mut editor, "$v0");
+        assert_eq!(selection(&mut editor), Some(0..11));
+
+        press(&mut editor, KeyCode::Char('z'), KeyModifiers::CONTROL);
+        assert_eq!(editor.focused_buffer().contents(), "ééééé");
+        assert_eq!(selection(&mut editor), Some(0..10));
+
+        // The anchor is still on a character boundary when the text gets put back, too. Redoing
+        // moves the cursor after the `x`.
+        press(&mut editor, KeyCode::Char('y'), KeyModifiers::CONTROL);
+        assert_eq!(selection(&mut editor), Some(1..11));
+    }
+
+    fn click(editor: &mut Editor, x: u16, y: u16) {
+        for kind in [
+            MouseEventKind::Down(MouseButton::Left),
+            MouseEventKind::Up(MouseButton::Left),
+        ] {
+            editor.handle_mouse(MouseEvent {
+                kind,
+                column: x,
+                row: y,
+                modifiers: KeyModifiers::NONE,
+            });
+        }
+    }
+
+    // Where redraw would put the terminal’s cursor.
+    fn cursor_screen_position(editor: &mut Editor) -> Option<(u16, u16)> {
+        editor.arrange_views(40, 25);
+
+        let view = editor.layout.view(editor.focused_view);
+        view.cursor_screen_position(&editor.buffers[view.buffer_idx])
+    }
+
+    #[test]
+    fn focusing_a_view_clamps_a_cursor_deleted_from_under_it() {
+        let lines: Vec<_> = (1..=100).map(|nr| nr.to_string()).collect();
+        let mut editor = editor(&lines.join("\n"));
+        editor.arrange_views(40, 25);
+
+        // Both views end up on the last line, and then the second deletes everything.
+        press(&mut editor, KeyCode::End, KeyModifiers::CONTROL);
+        press(&mut editor, KeyCode::Char('s'), KeyModifiers::ALT);
+        editor.arrange_views(40, 25);
+        press(
+            &mut editor,
+            KeyCode::Home,
+            KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+        );
+        press(&mut editor, KeyCode::Backspace, KeyModifiers::NONE);
+        assert_eq!(editor.focused_buffer().contents(), "");
+        assert_eq!(cursor_screen_position(&mut editor), Some((0, 12)));
+
+        // Clicking the first view’s status line focuses it without moving the cursor.
+        click(&mut editor, 0, 11);
+        assert_eq!(editor.focused_view, 0);
+        assert_eq!(cursor_screen_position(&mut editor), Some((0, 0)));
+
+        press(&mut editor, KeyCode::Char('w'), KeyModifiers::ALT);
+        press(&mut editor, KeyCode::Char('w'), KeyModifiers::ALT);
+        assert_eq!(cursor_screen_position(&mut editor), Some((0, 0)));
+    }
+
+    #[test]
+    fn quitting_with_unsaved_changes_has_to_be_confirmed() {
+        let mut editor = editor("text");
+
+        press(&mut editor, KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert!(editor.is_running());
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("test.txt not saved (quit again to discard the changes)")
+        );
+
+        // Anything else in between means starting over.
+        press(&mut editor, KeyCode::Right, KeyModifiers::NONE);
+        press(&mut editor, KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert!(editor.is_running());
+
+        press(&mut editor, KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert!(!editor.is_running());
+    }
+
+    #[test]
+    fn the_quit_command_has_to_be_forced_with_unsaved_changes() {
+        let mut editor = editor("text");
+
+        press(&mut editor, KeyCode::Char('p'), KeyModifiers::CONTROL);
+        type_chars(&mut editor, "quit");
+        press(&mut editor, KeyCode::Enter, KeyModifiers::NONE);
+        assert!(editor.is_running());
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("Error: test.txt not saved (use `quit!` to discard the changes)")
+        );
+
+        press(&mut editor, KeyCode::Char('p'), KeyModifiers::CONTROL);
+        type_chars(&mut editor, "quit!");
+        press(&mut editor, KeyCode::Enter, KeyModifiers::NONE);
+        assert!(!editor.is_running());
+    }
+
+    #[test]
+    fn quitting_without_changes_needs_no_confirmation() {
+        let mut editor = editor("text");
+
+        // Undoing back to where the buffer was loaded leaves nothing to save.
+        press(&mut editor, KeyCode::Char('z'), KeyModifiers::CONTROL);
+        press(&mut editor, KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert!(!editor.is_running());
+    }
+
+    #[test]
+    fn closing_a_new_split_focuses_the_view_it_came_from() {
+        let mut editor = editor("one\ntwo\nthree");
+        press(&mut editor, KeyCode::Down, KeyModifiers::NONE);
+        press(&mut editor, KeyCode::End, KeyModifiers::NONE);
+
+        press(&mut editor, KeyCode::Char('s'), KeyModifiers::ALT);
+        assert_eq!(editor.layout.len(), 2);
+        assert_eq!(editor.focused_view, 1);
+
+        press(&mut editor, KeyCode::Down, KeyModifiers::NONE);
+        press(&mut editor, KeyCode::Char('c'), KeyModifiers::ALT);
+        assert_eq!(editor.layout.len(), 1);
+        assert_eq!(editor.focused_view, 0);
+
+        // The remaining view’s cursor is back where it was left.
+        let buffer = editor.focused_buffer();
+        assert_eq!((buffer.line_nr(), buffer.col_nr()), (1, 3));
+    }
+
+    #[test]
+    fn closing_the_first_view_focuses_the_next() {
+        let mut editor = editor("one\ntwo\nthree");
+
+        press(&mut editor, KeyCode::Char('v'), KeyModifiers::ALT);
+        press(&mut editor, KeyCode::Down, KeyModifiers::NONE);
+        press(&mut editor, KeyCode::Char('w'), KeyModifiers::ALT);
+        assert_eq!(editor.focused_view, 0);
+
+        press(&mut editor, KeyCode::Char('c'), KeyModifiers::ALT);
+        assert_eq!(editor.layout.len(), 1);
+        assert_eq!(editor.focused_buffer().line_nr(), 1);
+
+        press(&mut editor, KeyCode::Char('c'), KeyModifiers::ALT);
+        assert_eq!(editor.layout.len(), 1);
+        assert!(editor.message.is_some());
+    }
+}
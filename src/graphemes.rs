@@ -0,0 +1,207 @@
+// Cursor columns are measured in grapheme clusters (what the user perceives as a single
+// character), while the text itself is indexed by bytes and laid out on the screen in terminal
+// cells. These functions convert between the three.
+//
+// Strings are measured as if they start at the left edge of the screen, which matters for tabs:
+// they are displayed as spaces up to the next tab stop, so how wide they are depends on where they
+// are. Other control characters are displayed in caret notation (e.g. `^M`), since writing them to
+// the terminal would move the cursor or worse.
+
+use {
+    std::borrow::Cow, unicode_segmentation::UnicodeSegmentation, unicode_width::UnicodeWidthChar,
+};
+
+// Tab stops are every this many columns.
+pub(crate) const TAB_WIDTH: usize = 4;
+
+pub(crate) fn count(s: &str) -> usize {
+    s.graphemes(true).count()
+}
+
+// Converts a grapheme index into a byte index, clamping to the end of the string.
+pub(crate) fn byte_idx(s: &str, grapheme_idx: usize) -> usize {
+    s.grapheme_indices(true)
+        .nth(grapheme_idx)
+        .map_or(s.len(), |(idx, _)| idx)
+}
+
+// Converts a byte index into the index of the grapheme that starts at or contains it.
+pub(crate) fn grapheme_idx(s: &str, byte_idx: usize) -> usize {
+    s.grapheme_indices(true)
+        .take_while(|(idx, _)| *idx < byte_idx)
+        .count()
+}
+
+// The number of terminal cells taken up by the first `grapheme_idx` graphemes of the string.
+pub(crate) fn display_col(s: &str, grapheme_idx: usize) -> usize {
+    widths(s).take(grapheme_idx).map(|(_, width)| width).sum()
+}
+
+// Converts a terminal column into the index of the grapheme displayed in it, or the number of
+// graphemes if the column is past the end of the string.
+pub(crate) fn grapheme_idx_at_col(s: &str, col: usize) -> usize {
+    let mut end_col = 0;
+
+    widths(s)
+        .take_while(|(_, width)| {
+            end_col += width;
+            end_col <= col
+        })
+        .count()
+}
+
+// The number of terminal cells taken up by the whole string.
+pub(crate) fn display_width(s: &str) -> usize {
+    widths(s).map(|(_, width)| width).sum()
+}
+
+// The number of terminal cells taken up by the grapheme at the given index, if there is one.
+pub(crate) fn width_at(s: &str, grapheme_idx: usize) -> Option<usize> {
+    widths(s).nth(grapheme_idx).map(|(_, width)| width)
+}
+
+// The number of terminal cells a single grapheme takes up, other than a control character.
+pub(crate) fn width(grapheme: &str) -> usize {
+    let mut chars = grapheme.chars();
+
+    // A cluster is as wide as its base character, since everything after that (combining marks,
+    // zero-width joiners and the like) is drawn on top of it. The exception is the emoji
+    // presentation selector, which turns a narrow symbol into a wide emoji.
+    let base_width = chars.next().and_then(UnicodeWidthChar::width).unwrap_or(0);
+
+    if chars.any(|c| c == '\u{fe0f}') {
+        2
+    } else {
+        base_width
+    }
+}
+
+// Each grapheme of the string along with the number of terminal cells it takes up where it is.
+fn widths(s: &str) -> impl Iterator<Item = (&str, usize)> {
+    let mut col = 0;
+
+    s.graphemes(true).map(move |grapheme| {
+        let width = displayed(grapheme, col).map_or_else(|| width(grapheme), |d| d.len());
+        col += width;
+
+        (grapheme, width)
+    })
+}
+
+// What is displayed in place of a control character that starts in the given column, which is
+// only ever made up of single-column ASCII characters.
+fn displayed(grapheme: &str, col: usize) -> Option<Cow<'static, str>> {
+    match grapheme.chars().next()? {
+        '\t' => Some(Cow::Owned(" ".repeat(TAB_WIDTH - col % TAB_WIDTH))),
+        c @ '\0'..='\x1f' => Some(Cow::Owned(format!("^{}", (c as u8 + b'@') as char))),
+        '\x7f' => Some(Cow::Borrowed("^?")),
+        c if c.is_control() => Some(Cow::Borrowed("?")),
+        _ => None,
+    }
+}
+
+// Replaces the control characters in a string that starts in the given column with what is
+// displayed in their place.
+pub(crate) fn expand(s: &str, start_col: usize) -> Cow<'_, str> {
+    if !s.chars().any(char::is_control) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut expanded = String::new();
+    let mut col = start_col;
+
+    for grapheme in s.graphemes(true) {
+        match displayed(grapheme, col) {
+            Some(displayed) => {
+                col += displayed.len();
+                expanded.push_str(&displayed);
+            }
+            None => {
+                col += width(grapheme);
+                expanded.push_str(grapheme);
+            }
+        }
+    }
+
+    Cow::Owned(expanded)
+}
+
+// Cuts out the part of the string that is displayed between the given terminal columns. Wide
+// graphemes that straddle either edge are replaced with spaces, so that everything after them
+// stays aligned.
+pub(crate) fn clip(s: &str, start_col: usize, width_in_cols: usize) -> String {
+    let end_col = start_col + width_in_cols;
+    let mut clipped = String::new();
+    let mut col = 0;
+
+    for grapheme in expand(s, 0).graphemes(true) {
+        let grapheme_width = width(grapheme);
+        let grapheme_end_col = col + grapheme_width;
+
+        if grapheme_end_col > end_col {
+            clipped.push_str(&" ".repeat(end_col.saturating_sub(col.max(start_col))));
+            break;
+        }
+
+        if col >= start_col {
+            clipped.push_str(grapheme);
+        } else if grapheme_end_col > start_col {
+            clipped.push_str(&" ".repeat(grapheme_end_col - start_col));
+        }
+
+        col = grapheme_end_col;
+    }
+
+    clipped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tabs_go_up_to_the_next_tab_stop() {
+        assert_eq!(display_width("\t"), TAB_WIDTH);
+        assert_eq!(display_width("ab\t"), TAB_WIDTH);
+        assert_eq!(display_width("abcd\tx"), TAB_WIDTH * 2 + 1);
+        assert_eq!(display_col("a\tb", 2), TAB_WIDTH);
+        assert_eq!(width_at("ab\t", 2), Some(TAB_WIDTH - 2));
+    }
+
+    #[test]
+    fn columns_in_a_tab_are_on_it() {
+        assert_eq!(grapheme_idx_at_col("a\tb", 1), 1);
+        assert_eq!(grapheme_idx_at_col("a\tb", TAB_WIDTH - 1), 1);
+        assert_eq!(grapheme_idx_at_col("a\tb", TAB_WIDTH), 2);
+    }
+
+    #[test]
+    fn control_characters_are_expanded() {
+        assert_eq!(
+            expand("a\tb", 0),
+            format!("a{}b", " ".repeat(TAB_WIDTH - 1))
+        );
+        assert_eq!(expand("\t", 1), " ".repeat(TAB_WIDTH - 1));
+        assert_eq!(expand("a\rb\x7f", 0), "a^Mb^?");
+        assert_eq!(display_width("a\rb"), 4);
+    }
+
+    #[test]
+    fn clipping_never_includes_control_characters() {
+        assert_eq!(
+            clip("\tx", 0, TAB_WIDTH + 1),
+            format!("{}x", " ".repeat(TAB_WIDTH))
+        );
+        assert_eq!(
+            clip("\tx", 2, 10),
+            format!("{}x", " ".repeat(TAB_WIDTH - 2))
+        );
+        assert_eq!(clip("a\x1bb", 0, 10), "a^[b");
+    }
+
+    #[test]
+    fn wide_graphemes_straddling_the_edge_become_spaces() {
+        assert_eq!(clip("a中b", 2, 10), " b");
+        assert_eq!(clip("a中b", 0, 2), "a ");
+    }
+}
@@ -0,0 +1,240 @@
+use crate::text::Position;
+
+// The smallest unit of modification a buffer’s text can undergo. Every edit to a buffer is made
+// up of these, which means that each one can be undone by applying its inverse.
+#[derive(Clone, Debug)]
+pub(crate) enum Change {
+    Insert { at: Position, text: String },
+    Delete { at: Position, text: String },
+}
+
+impl Change {
+    pub(crate) fn inverse(&self) -> Self {
+        match self {
+            Self::Insert { at, text } => Self::Delete {
+                at: *at,
+                text: text.clone(),
+            },
+            Self::Delete { at, text } => Self::Insert {
+                at: *at,
+                text: text.clone(),
+            },
+        }
+    }
+}
+
+// Consecutive edits of the same kind (e.g. typing a word character by character) are grouped
+// into a single undo step. Edits of kind `Other` are never grouped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum EditKind {
+    Typing,
+    Deleting,
+    Other,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Step {
+    pub(crate) changes: Vec<Change>,
+    pub(crate) cursor_before: Position,
+    pub(crate) cursor_after: Position,
+    kind: EditKind,
+    // Identifies the state of the text after this step, for telling whether it has been saved.
+    id: usize,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct History {
+    undo_stack: Vec<Step>,
+    redo_stack: Vec<Step>,
+    last_id: usize,
+    // While edits are being grouped, the size the undo stack had when the group began.
+    group_start: Option<usize>,
+    // Groups can be begun inside other groups (e.g. replacing the selection while typing in Vi’s
+    // insert mode), in which case they are part of the outermost one.
+    group_depth: usize,
+}
+
+impl History {
+    pub(crate) fn record(
+        &mut self,
+        change: Change,
+        cursor_before: Position,
+        cursor_after: Position,
+        kind: EditKind,
+    ) {
+        // Making a new edit means that whatever was undone can no longer be redone.
+        self.redo_stack.clear();
+
+        let undo_stack_len = self.undo_stack.len();
+        let is_in_group = self
+            .group_start
+            .is_some_and(|group_start| undo_stack_len > group_start);
+
+        // An edit continues the previous step if it’s part of the same group, or if it’s of the
+        // same kind and starts where the previous one left the cursor. Moving the cursor in
+        // between therefore starts a new step.
+        if let Some(step) = self.undo_stack.last_mut() {
+            if is_in_group
+                || kind != EditKind::Other
+                    && step.kind == kind
+                    && step.cursor_after == cursor_before
+            {
+                step.changes.push(change);
+                step.cursor_after = cursor_after;
+
+                // The text is no longer in the state the step used to leave it in.
+                self.last_id += 1;
+                step.id = self.last_id;
+
+                return;
+            }
+        }
+
+        self.last_id += 1;
+        self.undo_stack.push(Step {
+            changes: vec![change],
+            cursor_before,
+            cursor_after,
+            kind,
+            id: self.last_id,
+        });
+    }
+
+    // Everything recorded between these two calls makes up a single step, no matter where in the
+    // text it happens or how far the cursor moves in between, e.g. every replacement made by a
+    // find-and-replace.
+    //
+    // Neither the first edit of a group nor the first edit after it continues the step before it,
+    // even if it is of the same kind and starts where that step left the cursor.
+    pub(crate) fn begin_group(&mut self) {
+        if self.group_depth == 0 {
+            self.seal_last_step();
+            self.group_start = Some(self.undo_stack.len());
+        }
+
+        self.group_depth += 1;
+    }
+
+    pub(crate) fn end_group(&mut self) {
+        match self.group_depth {
+            0 => {}
+            1 => {
+                self.group_depth = 0;
+                self.group_start = None;
+                self.seal_last_step();
+            }
+            _ => self.group_depth -= 1,
+        }
+    }
+
+    // Stops later edits from being added to the last step.
+    fn seal_last_step(&mut self) {
+        if let Some(step) = self.undo_stack.last_mut() {
+            step.kind = EditKind::Other;
+        }
+    }
+
+    // Identifies the current state of the text. Undoing and redoing back to a state gives the same
+    // value as before, whereas any new edit gives a value that has never been seen before.
+    pub(crate) fn state(&self) -> usize {
+        self.undo_stack.last().map_or(0, |step| step.id)
+    }
+
+    // The caller is responsible for applying the inverses of the returned step’s changes in
+    // reverse order.
+    pub(crate) fn undo(&mut self) -> Option<Step> {
+        let step = self.undo_stack.pop()?;
+        self.redo_stack.push(step.clone());
+
+        Some(step)
+    }
+
+    // The caller is responsible for reapplying the returned step’s changes in order.
+    pub(crate) fn redo(&mut self) -> Option<Step> {
+        let mut step = self.redo_stack.pop()?;
+
+        // Redone steps should never have later edits grouped into them.
+        step.kind = EditKind::Other;
+        self.undo_stack.push(step.clone());
+
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_char(history: &mut History, col: usize, c: char) {
+        history.record(
+            Change::Insert {
+                at: Position { line: 0, col },
+                text: c.to_string(),
+            },
+            Position { line: 0, col },
+            Position {
+                line: 0,
+                col: col + 1,
+            },
+            EditKind::Typing,
+        );
+    }
+
+    fn type_str(history: &mut History, col: usize, s: &str) {
+        for (idx, c) in s.chars().enumerate() {
+            type_char(history, col + idx, c);
+        }
+    }
+
+    #[test]
+    fn consecutive_typing_is_one_step() {
+        let mut history = History::default();
+        type_str(&mut history, 0, "hello");
+
+        assert!(history.undo().is_some());
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn group_starts_a_new_step() {
+        let mut history = History::default();
+        type_str(&mut history, 0, "hello");
+
+        history.begin_group();
+        type_str(&mut history, 5, " world");
+        history.end_group();
+
+        assert_eq!(history.undo().unwrap().changes.len(), 6);
+        assert_eq!(history.undo().unwrap().changes.len(), 5);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn nested_groups_are_part_of_the_outer_one() {
+        let mut history = History::default();
+
+        history.begin_group();
+        type_str(&mut history, 0, "a");
+        history.begin_group();
+        type_str(&mut history, 1, "b");
+        history.end_group();
+        type_str(&mut history, 2, "c");
+        history.end_group();
+
+        assert_eq!(history.undo().unwrap().changes.len(), 3);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn typing_after_group_starts_a_new_step() {
+        let mut history = History::default();
+
+        history.begin_group();
+        type_str(&mut history, 0, "hello");
+        history.end_group();
+        type_str(&mut history, 5, "!");
+
+        assert_eq!(history.undo().unwrap().changes.len(), 1);
+        assert_eq!(history.undo().unwrap().changes.len(), 5);
+    }
+}
@@ -0,0 +1,499 @@
+// Which keys do what. Keys are written the way Emacs writes them: `C-` for control, `M-` for alt
+// and `S-` for shift, followed by either a character or the name of a key, e.g. `C-s`, `M-n` or
+// `S-up`. A chord is several keys pressed one after the other, separated by spaces, e.g.
+// `C-x C-s`.
+//
+// Any key that isn’t bound to an action and has no modifiers (other than shift) inserts itself.
+
+use {
+    crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
+    std::{fmt, str::FromStr},
+};
+
+// Everything a key can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveLineStart,
+    MoveLineEnd,
+    MoveBufferStart,
+    MoveBufferEnd,
+    PageUp,
+    PageDown,
+    SelectUp,
+    SelectDown,
+    SelectLeft,
+    SelectRight,
+    SelectLineStart,
+    SelectLineEnd,
+    SelectBufferStart,
+    SelectBufferEnd,
+    SelectPageUp,
+    SelectPageDown,
+    Backspace,
+    Newline,
+    Cut,
+    Copy,
+    Paste,
+    Save,
+    Undo,
+    Redo,
+    CycleTheme,
+    CommandLine,
+    Open,
+    SwitchBuffer,
+    Search,
+    NextMatch,
+    PreviousMatch,
+    ClearSearch,
+    Replace,
+    SplitHorizontal,
+    SplitVertical,
+    FocusNextView,
+    CloseView,
+    ListBindings,
+    Quit,
+}
+
+// The name of every action, which is how they are referred to in the config file and the list of
+// bindings.
+const ACTIONS: &[(&str, Action)] = &[
+    ("move-up", Action::MoveUp),
+    ("move-down", Action::MoveDown),
+    ("move-left", Action::MoveLeft),
+    ("move-right", Action::MoveRight),
+    ("move-line-start", Action::MoveLineStart),
+    ("move-line-end", Action::MoveLineEnd),
+    ("move-buffer-start", Action::MoveBufferStart),
+    ("move-buffer-end", Action::MoveBufferEnd),
+    ("page-up", Action::PageUp),
+    ("page-down", Action::PageDown),
+    ("select-up", Action::SelectUp),
+    ("select-down", Action::SelectDown),
+    ("select-left", Action::SelectLeft),
+    ("select-right", Action::SelectRight),
+    ("select-line-start", Action::SelectLineStart),
+    ("select-line-end", Action::SelectLineEnd),
+    ("select-buffer-start", Action::SelectBufferStart),
+    ("select-buffer-end", Action::SelectBufferEnd),
+    ("select-page-up", Action::SelectPageUp),
+    ("select-page-down", Action::SelectPageDown),
+    ("backspace", Action::Backspace),
+    ("newline", Action::Newline),
+    ("cut", Action::Cut),
+    ("copy", Action::Copy),
+    ("paste", Action::Paste),
+    ("save", Action::Save),
+    ("undo", Action::Undo),
+    ("redo", Action::Redo),
+    ("cycle-theme", Action::CycleTheme),
+    ("command-line", Action::CommandLine),
+    ("open", Action::Open),
+    ("switch-buffer", Action::SwitchBuffer),
+    ("search", Action::Search),
+    ("next-match", Action::NextMatch),
+    ("previous-match", Action::PreviousMatch),
+    ("clear-search", Action::ClearSearch),
+    ("replace", Action::Replace),
+    ("split-horizontal", Action::SplitHorizontal),
+    ("split-vertical", Action::SplitVertical),
+    ("focus-next-view", Action::FocusNextView),
+    ("close-view", Action::CloseView),
+    ("list-bindings", Action::ListBindings),
+    ("quit", Action::Quit),
+];
+
+impl Action {
+    fn name(self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(_, action)| *action == self)
+            .map(|(name, _)| *name)
+            .unwrap()
+    }
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        ACTIONS
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, action)| *action)
+            .ok_or_else(|| anyhow::anyhow!("unknown action `{}`", s))
+    }
+}
+
+// A single key press. Shift is left out of the modifiers of characters, since it is already
+// reflected in the character itself (`A` rather than `a`), and likewise for shift-tab.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl From<KeyEvent> for Key {
+    fn from(key: KeyEvent) -> Self {
+        let modifiers = match key.code {
+            KeyCode::Char(_) | KeyCode::BackTab => key.modifiers - KeyModifiers::SHIFT,
+            _ => key.modifiers,
+        };
+
+        Self {
+            code: key.code,
+            modifiers,
+        }
+    }
+}
+
+// Keys with names, as opposed to those written as the character they type.
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("enter", KeyCode::Enter),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("esc", KeyCode::Esc),
+    ("space", KeyCode::Char(' ')),
+];
+
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+
+        // A lone `-` is a key in its own right, rather than a modifier with nothing after it.
+        while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+            modifiers |= match &rest[..1] {
+                "C" => KeyModifiers::CONTROL,
+                "M" => KeyModifiers::ALT,
+                "S" => KeyModifiers::SHIFT,
+                modifier => anyhow::bail!("unknown modifier `{}-` in `{}`", modifier, s),
+            };
+            rest = &rest[2..];
+        }
+
+        let mut chars = rest.chars();
+
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match KEY_NAMES.iter().find(|(name, _)| *name == rest) {
+                Some((_, code)) => *code,
+                None => match rest.strip_prefix('f').and_then(|nr| nr.parse().ok()) {
+                    Some(nr) if (1..=12).contains(&nr) => KeyCode::F(nr),
+                    _ => anyhow::bail!("unknown key `{}` in `{}`", rest, s),
+                },
+            },
+        };
+
+        Ok(Self::from(KeyEvent::new(code, modifiers)))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "C-")?;
+        }
+
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "M-")?;
+        }
+
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "S-")?;
+        }
+
+        match self.code {
+            KeyCode::F(nr) => write!(f, "f{}", nr),
+            KeyCode::Char(c) if c != ' ' => write!(f, "{}", c),
+            code => match KEY_NAMES.iter().find(|(_, named)| *named == code) {
+                Some((name, _)) => write!(f, "{}", name),
+                None => write!(f, "{:?}", code),
+            },
+        }
+    }
+}
+
+// Parses a chord, i.e. a sequence of keys separated by spaces.
+pub(crate) fn parse_keys(s: &str) -> anyhow::Result<Vec<Key>> {
+    let keys = s
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if keys.is_empty() {
+        anyhow::bail!("no keys given");
+    }
+
+    Ok(keys)
+}
+
+// Whether two chords are the same, or one is the start of the other, which means they can’t both
+// be bound.
+pub(crate) fn overlap(a: &[Key], b: &[Key]) -> bool {
+    let shared = a.len().min(b.len());
+    a[..shared] == b[..shared]
+}
+
+fn display_keys(keys: &[Key]) -> String {
+    keys.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// What a sequence of keys typed so far amounts to.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Lookup {
+    Action(Action),
+    // The keys are the start of at least one chord, so we have to wait for the next key.
+    Prefix,
+    Unbound,
+}
+
+#[derive(Debug)]
+pub(crate) struct Keymap {
+    // Kept in the order they were bound, which is the order they are listed in.
+    bindings: Vec<(Vec<Key>, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let defaults = [
+            ("up", Action::MoveUp),
+            ("down", Action::MoveDown),
+            ("left", Action::MoveLeft),
+            ("right", Action::MoveRight),
+            ("home", Action::MoveLineStart),
+            ("end", Action::MoveLineEnd),
+            ("C-home", Action::MoveBufferStart),
+            ("C-end", Action::MoveBufferEnd),
+            ("pageup", Action::PageUp),
+            ("pagedown", Action::PageDown),
+            ("S-up", Action::SelectUp),
+            ("S-down", Action::SelectDown),
+            ("S-left", Action::SelectLeft),
+            ("S-right", Action::SelectRight),
+            ("S-home", Action::SelectLineStart),
+            ("S-end", Action::SelectLineEnd),
+            ("C-S-home", Action::SelectBufferStart),
+            ("C-S-end", Action::SelectBufferEnd),
+            ("S-pageup", Action::SelectPageUp),
+            ("S-pagedown", Action::SelectPageDown),
+            ("backspace", Action::Backspace),
+            ("enter", Action::Newline),
+            ("C-x", Action::Cut),
+            ("C-c", Action::Copy),
+            ("C-v", Action::Paste),
+            ("C-s", Action::Save),
+            ("C-z", Action::Undo),
+            ("C-y", Action::Redo),
+            ("C-t", Action::CycleTheme),
+            ("C-p", Action::CommandLine),
+            ("C-o", Action::Open),
+            ("C-b", Action::SwitchBuffer),
+            ("C-f", Action::Search),
+            ("M-n", Action::NextMatch),
+            ("M-p", Action::PreviousMatch),
+            ("esc", Action::ClearSearch),
+            ("C-r", Action::Replace),
+            // Splitting views works like it does in Vim, except with Alt instead of C-w as the
+            // prefix.
+            ("M-s", Action::SplitHorizontal),
+            ("M-v", Action::SplitVertical),
+            ("M-w", Action::FocusNextView),
+            ("M-c", Action::CloseView),
+            ("f1", Action::ListBindings),
+            ("C-q", Action::Quit),
+        ];
+
+        Self {
+            bindings: defaults
+                .iter()
+                .map(|(keys, action)| (parse_keys(keys).unwrap(), *action))
+                .collect(),
+        }
+    }
+}
+
+impl Keymap {
+    // Binds the keys written in the config file to an action, or unbinds them if the action is
+    // `none`. A chord can’t share its first keys with a shorter binding (there would be no way to
+    // tell which one was meant), so any such bindings are replaced.
+    pub(crate) fn bind(&mut self, keys: &str, action: &str) -> anyhow::Result<()> {
+        let keys = parse_keys(keys)?;
+        let action = match action {
+            "none" => None,
+            action => Some(action.parse()?),
+        };
+
+        self.bindings.retain(|(bound, _)| !overlap(bound, &keys));
+
+        if let Some(action) = action {
+            self.bindings.push((keys, action));
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn lookup(&self, keys: &[Key]) -> Lookup {
+        let mut lookup = Lookup::Unbound;
+
+        for (bound, action) in &self.bindings {
+            if bound.as_slice() == keys {
+                return Lookup::Action(*action);
+            }
+
+            if bound.starts_with(keys) {
+                lookup = Lookup::Prefix;
+            }
+        }
+
+        lookup
+    }
+
+    // Every binding as the keys and the name of the action they are bound to.
+    pub(crate) fn bindings(&self) -> Vec<(String, &'static str)> {
+        self.bindings
+            .iter()
+            .map(|(keys, action)| (display_keys(keys), action.name()))
+            .collect()
+    }
+}
+
+// Keys typed so far that might be the start of a chord, for showing on the message line.
+pub(crate) fn pending_message(keys: &[Key]) -> String {
+    format!("{}-", display_keys(keys))
+}
+
+pub(crate) fn unbound_message(keys: &[Key]) -> String {
+    format!("`{}` isn’t bound to anything", display_keys(keys))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Key {
+        Key { code, modifiers }
+    }
+
+    #[test]
+    fn keys_are_parsed_with_modifiers() {
+        assert_eq!(
+            "a".parse::<Key>().unwrap(),
+            key(KeyCode::Char('a'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            "C-M-x".parse::<Key>().unwrap(),
+            key(
+                KeyCode::Char('x'),
+                KeyModifiers::CONTROL | KeyModifiers::ALT
+            )
+        );
+        assert_eq!(
+            "S-up".parse::<Key>().unwrap(),
+            key(KeyCode::Up, KeyModifiers::SHIFT)
+        );
+        assert_eq!(
+            "f12".parse::<Key>().unwrap(),
+            key(KeyCode::F(12), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            "-".parse::<Key>().unwrap(),
+            key(KeyCode::Char('-'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            "C--".parse::<Key>().unwrap(),
+            key(KeyCode::Char('-'), KeyModifiers::CONTROL)
+        );
+    }
+
+    #[test]
+    fn shift_is_part_of_the_character() {
+        assert_eq!(
+            "S-a".parse::<Key>().unwrap(),
+            key(KeyCode::Char('a'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            Key::from(KeyEvent::new(KeyCode::Char('A'), KeyModifiers::SHIFT)),
+            key(KeyCode::Char('A'), KeyModifiers::NONE)
+        );
+    }
+
+    #[test]
+    fn bad_keys_are_errors() {
+        assert_eq!(
+            "X-a".parse::<Key>().unwrap_err().to_string(),
+            "unknown modifier `X-` in `X-a`"
+        );
+        assert_eq!(
+            "C-foo".parse::<Key>().unwrap_err().to_string(),
+            "unknown key `foo` in `C-foo`"
+        );
+        assert!("f13".parse::<Key>().is_err());
+        assert!(parse_keys(" ").is_err());
+    }
+
+    #[test]
+    fn keys_are_displayed_as_they_are_written() {
+        for keys in &["C-x", "M-s", "C-S-home", "f1", "space", "C-w v"] {
+            assert_eq!(display_keys(&parse_keys(keys).unwrap()), *keys);
+        }
+    }
+
+    #[test]
+    fn chords_wait_for_their_last_key() {
+        let mut keymap = Keymap::default();
+        keymap.bind("C-w v", "split-vertical").unwrap();
+
+        let keys = parse_keys("C-w v").unwrap();
+        assert_eq!(keymap.lookup(&keys[..1]), Lookup::Prefix);
+        assert_eq!(keymap.lookup(&keys), Lookup::Action(Action::SplitVertical));
+        assert_eq!(
+            keymap.lookup(&parse_keys("C-w x").unwrap()),
+            Lookup::Unbound
+        );
+    }
+
+    #[test]
+    fn binding_a_chord_replaces_bindings_it_conflicts_with() {
+        let mut keymap = Keymap::default();
+        let lookup = |keymap: &Keymap, keys| keymap.lookup(&parse_keys(keys).unwrap());
+
+        // `C-x` can’t be both an action and the start of a chord.
+        keymap.bind("C-x C-s", "save").unwrap();
+        assert_eq!(lookup(&keymap, "C-x"), Lookup::Prefix);
+        assert_eq!(lookup(&keymap, "C-x C-s"), Lookup::Action(Action::Save));
+
+        // Nor can a chord carry on from a shorter one.
+        keymap.bind("C-x", "cut").unwrap();
+        assert_eq!(lookup(&keymap, "C-x"), Lookup::Action(Action::Cut));
+        assert_eq!(lookup(&keymap, "C-x C-s"), Lookup::Unbound);
+    }
+
+    #[test]
+    fn none_unbinds() {
+        let mut keymap = Keymap::default();
+        keymap.bind("C-z", "none").unwrap();
+
+        assert_eq!(keymap.lookup(&parse_keys("C-z").unwrap()), Lookup::Unbound);
+        assert!(keymap.bind("C-z", "frobnicate").is_err());
+    }
+}
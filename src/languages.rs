@@ -0,0 +1,241 @@
+use {
+    crate::text::Text,
+    std::{fmt, path::Path},
+};
+
+// A language the editor recognises, along with everything needed to detect it and highlight it.
+// Languages without a highlighter of their own are displayed as plain text, but are still detected
+// so that the user can see what the editor thinks they’re editing.
+pub(crate) struct Language {
+    pub(crate) name: &'static str,
+    // The names the language can be referred to by in modelines.
+    aliases: &'static [&'static str],
+    extensions: &'static [&'static str],
+    // The programs that, when named in a shebang, mark a file as being written in this language.
+    interpreters: &'static [&'static str],
+    pub(crate) highlighter: &'static dyn dialect::Highlight,
+}
+
+impl fmt::Debug for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Language")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        name: "Rust",
+        aliases: &["rust", "rs"],
+        extensions: &["rs"],
+        interpreters: &["rust-script", "run-cargo-script"],
+        highlighter: &syntax_rust::RustHighlighter,
+    },
+    Language {
+        name: "Markdown",
+        aliases: &["markdown", "md"],
+        extensions: &["md", "markdown"],
+        interpreters: &[],
+        highlighter: &PlainText,
+    },
+    Language {
+        name: "TOML",
+        aliases: &["toml"],
+        extensions: &["toml"],
+        interpreters: &[],
+        highlighter: &PlainText,
+    },
+    Language {
+        name: "Shell",
+        aliases: &["sh", "bash", "zsh", "shell", "shell-script"],
+        extensions: &["sh", "bash", "zsh"],
+        interpreters: &["sh", "bash", "zsh", "dash", "ksh"],
+        highlighter: &PlainText,
+    },
+];
+
+const PLAIN_TEXT: &Language = &Language {
+    name: "Plain Text",
+    aliases: &["text", "txt"],
+    extensions: &["txt"],
+    interpreters: &[],
+    highlighter: &PlainText,
+};
+
+// Works out the language of a file. What the file says about itself is trusted over what its name
+// says, so modelines come first, followed by shebangs and finally file extensions. Anything that
+// can’t be identified is treated as plain text.
+pub(crate) fn detect(path: &Path, text: &Text) -> &'static Language {
+    from_modeline(text)
+        .or_else(|| from_shebang(&text.line(0)))
+        .or_else(|| from_extension(path))
+        .unwrap_or(PLAIN_TEXT)
+}
+
+fn all() -> impl Iterator<Item = &'static Language> {
+    LANGUAGES.iter().chain(std::iter::once(PLAIN_TEXT))
+}
+
+fn from_extension(path: &Path) -> Option<&'static Language> {
+    let extension = path.extension()?.to_str()?;
+    all().find(|language| language.extensions.contains(&extension))
+}
+
+// Shebangs look like `#!/bin/sh` or `#!/usr/bin/env bash`.
+fn from_shebang(first_line: &str) -> Option<&'static Language> {
+    let command = first_line.strip_prefix("#!")?;
+
+    // Rust’s inner attributes (`#![…]`) look just like shebangs, so we have to rule them out.
+    if command.trim_start().starts_with('[') {
+        return None;
+    }
+
+    let mut words = command.split_whitespace();
+    let program = program_name(words.next()?);
+
+    // With `env` the interpreter is the first argument that isn’t a flag.
+    let interpreter = if program == "env" {
+        program_name(words.find(|word| !word.starts_with('-'))?)
+    } else {
+        program
+    };
+
+    all().find(|language| language.interpreters.contains(&interpreter))
+}
+
+fn program_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+// Modelines can appear in the first or last few lines of a file. We support both Vim-style
+// (`vim: set ft=rust:`) and Emacs-style (`-*- mode: rust -*-`) modelines.
+fn from_modeline(text: &Text) -> Option<&'static Language> {
+    const MODELINE_LINES: usize = 5;
+
+    let len_lines = text.len_lines();
+    let last_lines_start = len_lines.saturating_sub(MODELINE_LINES).max(MODELINE_LINES);
+
+    let name = (0..MODELINE_LINES.min(len_lines))
+        .chain(last_lines_start..len_lines)
+        .find_map(|line_nr| {
+            let line = text.line(line_nr);
+            vim_modeline(&line)
+                .or_else(|| emacs_modeline(&line))
+                .map(str::to_lowercase)
+        })?;
+
+    all().find(|language| language.aliases.contains(&name.as_str()))
+}
+
+fn vim_modeline(line: &str) -> Option<&str> {
+    // The marker has to be at the start of a word, so that e.g. `navi:` doesn’t count.
+    let options = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|(idx, _)| {
+                line[..*idx]
+                    .chars()
+                    .next_back()
+                    .is_none_or(char::is_whitespace)
+            })
+            .map(|(idx, _)| &line[idx + marker.len()..])
+    })?;
+
+    // Options are separated by spaces or colons, and may be preceded by `set`.
+    options
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .find_map(|option| {
+            option
+                .strip_prefix("ft=")
+                .or_else(|| option.strip_prefix("filetype="))
+        })
+}
+
+fn emacs_modeline(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (variables, _) = rest.split_once("-*-")?;
+
+    // The modeline is either just the mode (`-*- rust -*-`) or a list of variables
+    // (`-*- mode: rust; fill-column: 100 -*-`).
+    if !variables.contains(':') {
+        return Some(variables.trim());
+    }
+
+    variables.split(';').find_map(|variable| {
+        let (name, value) = variable.split_once(':')?;
+
+        if name.trim() == "mode" {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+// The highlighter for languages we can’t highlight, which leaves all text unhighlighted.
+struct PlainText;
+
+impl dialect::Highlight for PlainText {
+    fn highlight<'input>(&self, input: &'input str) -> Vec<dialect::HighlightedSpan<'input>> {
+        vec![dialect::HighlightedSpan {
+            text: input,
+            group: None,
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect_name(path: &str, contents: &str) -> &'static str {
+        detect(Path::new(path), &Text::from_file_contents(contents)).name
+    }
+
+    #[test]
+    fn languages_are_detected_by_extension() {
+        assert_eq!(detect_name("src/main.rs", ""), "Rust");
+        assert_eq!(detect_name("README.md", ""), "Markdown");
+        assert_eq!(detect_name("Cargo.toml", ""), "TOML");
+        assert_eq!(detect_name("notes.txt", ""), "Plain Text");
+    }
+
+    #[test]
+    fn unknown_files_are_plain_text() {
+        assert_eq!(detect_name("photo.xyz", "some text"), "Plain Text");
+        assert_eq!(detect_name("Makefile", "all:"), "Plain Text");
+    }
+
+    #[test]
+    fn shebangs_name_the_interpreter() {
+        assert_eq!(detect_name("script", "#!/bin/sh\necho"), "Shell");
+        assert_eq!(detect_name("script", "#!/usr/bin/env -S bash -e"), "Shell");
+        assert_eq!(
+            detect_name("script", "#!/usr/bin/env python3"),
+            "Plain Text"
+        );
+
+        // Inner attributes aren’t shebangs.
+        assert_eq!(detect_name("lib.rs", "#![allow(dead_code)]"), "Rust");
+    }
+
+    #[test]
+    fn modelines_can_be_at_either_end_of_the_file() {
+        assert_eq!(detect_name("file", "# vim: set ft=sh:\necho"), "Shell");
+        assert_eq!(detect_name("file", "// -*- mode: Rust -*-"), "Rust");
+        assert_eq!(
+            detect_name("file", &("x\n".repeat(20) + "<!-- vi:ft=md -->")),
+            "Markdown"
+        );
+
+        // Markers in the middle of a word don’t count.
+        assert_eq!(detect_name("file", "navi:ft=rust"), "Plain Text");
+    }
+
+    #[test]
+    fn modelines_win_over_shebangs_which_win_over_extensions() {
+        assert_eq!(detect_name("run.rs", "#!/bin/bash\n# vim: ft=toml"), "TOML");
+        assert_eq!(detect_name("run.rs", "#!/bin/bash"), "Shell");
+        assert_eq!(detect_name("run.sh", "# -*- rust -*-"), "Rust");
+    }
+}
@@ -0,0 +1,309 @@
+use crate::view::View;
+
+// A rectangle of terminal cells.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Rect {
+    pub(crate) x: u16,
+    pub(crate) y: u16,
+    pub(crate) cols: u16,
+    pub(crate) lines: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SplitDirection {
+    // One view above the other.
+    Horizontal,
+    // One view beside the other.
+    Vertical,
+}
+
+// How the screen is divided up between views. Every split divides its space in two, so the layout
+// is a binary tree with a view at each leaf. Views are numbered in the order they appear in the
+// tree, which is also the order focus moves through them.
+#[derive(Debug)]
+pub(crate) enum Layout {
+    View(View),
+    Split {
+        direction: SplitDirection,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    pub(crate) fn views(&self) -> Vec<&View> {
+        match self {
+            Self::View(view) => vec![view],
+            Self::Split { first, second, .. } => {
+                let mut views = first.views();
+                views.extend(second.views());
+                views
+            }
+        }
+    }
+
+    pub(crate) fn views_mut(&mut self) -> Vec<&mut View> {
+        match self {
+            Self::View(view) => vec![view],
+            Self::Split { first, second, .. } => {
+                let mut views = first.views_mut();
+                views.extend(second.views_mut());
+                views
+            }
+        }
+    }
+
+    pub(crate) fn view(&self, idx: usize) -> &View {
+        self.views()[idx]
+    }
+
+    pub(crate) fn view_mut(&mut self, idx: usize) -> &mut View {
+        self.views_mut().swap_remove(idx)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::View(_) => 1,
+            Self::Split { first, second, .. } => first.len() + second.len(),
+        }
+    }
+
+    // Splits the view with the given index in two, with both halves showing the same part of the
+    // same buffer. The new view comes straight after the old one.
+    pub(crate) fn split(&mut self, idx: usize, direction: SplitDirection) {
+        match self {
+            Self::View(view) => {
+                let first = Box::new(Self::View(view.clone()));
+                let second = Box::new(Self::View(view.clone()));
+
+                *self = Self::Split {
+                    direction,
+                    first,
+                    second,
+                };
+            }
+            Self::Split { first, second, .. } => {
+                let first_len = first.len();
+
+                if idx < first_len {
+                    first.split(idx, direction);
+                } else {
+                    second.split(idx - first_len, direction);
+                }
+            }
+        }
+    }
+
+    // Removes the view with the given index, giving its space to the other half of the split it
+    // was in. Returns `None` if that was the only view.
+    pub(crate) fn close(self, idx: usize) -> Option<Self> {
+        match self {
+            Self::View(_) => None,
+            Self::Split {
+                direction,
+                first,
+                second,
+            } => {
+                let first_len = first.len();
+
+                Some(if idx < first_len {
+                    match first.close(idx) {
+                        Some(first) => Self::Split {
+                            direction,
+                            first: Box::new(first),
+                            second,
+                        },
+                        None => *second,
+                    }
+                } else {
+                    match second.close(idx - first_len) {
+                        Some(second) => Self::Split {
+                            direction,
+                            first,
+                            second: Box::new(second),
+                        },
+                        None => *first,
+                    }
+                })
+            }
+        }
+    }
+
+    // Works out where each view goes within the given area, in the same order as `views`, along
+    // with the separators between views that are side by side. Views that are above one another
+    // don’t need one, since each view’s status line already marks where it ends.
+    pub(crate) fn arrange(&self, area: Rect) -> (Vec<Rect>, Vec<Rect>) {
+        let mut rects = Vec::new();
+        let mut separators = Vec::new();
+        self.arrange_into(area, &mut rects, &mut separators);
+
+        (rects, separators)
+    }
+
+    fn arrange_into(&self, area: Rect, rects: &mut Vec<Rect>, separators: &mut Vec<Rect>) {
+        match self {
+            Self::View(_) => rects.push(area),
+            Self::Split {
+                direction,
+                first,
+                second,
+            } => {
+                // Space is shared as evenly as possible, with the first half getting any odd one
+                // out.
+                match direction {
+                    SplitDirection::Horizontal => {
+                        let second_lines = area.lines / 2;
+                        let first_lines = area.lines - second_lines;
+
+                        first.arrange_into(
+                            Rect {
+                                lines: first_lines,
+                                ..area
+                            },
+                            rects,
+                            separators,
+                        );
+                        second.arrange_into(
+                            Rect {
+                                y: area.y + first_lines,
+                                lines: second_lines,
+                                ..area
+                            },
+                            rects,
+                            separators,
+                        );
+                    }
+                    SplitDirection::Vertical => {
+                        // The separator takes up a column of its own.
+                        let available = area.cols.saturating_sub(1);
+                        let second_cols = available / 2;
+                        let first_cols = available - second_cols;
+
+                        first.arrange_into(
+                            Rect {
+                                cols: first_cols,
+                                ..area
+                            },
+                            rects,
+                            separators,
+                        );
+                        separators.push(Rect {
+                            x: area.x + first_cols,
+                            cols: area.cols.min(1),
+                            ..area
+                        });
+                        second.arrange_into(
+                            Rect {
+                                x: area.x + area.cols - second_cols,
+                                cols: second_cols,
+                                ..area
+                            },
+                            rects,
+                            separators,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer_idxs(layout: &Layout) -> Vec<usize> {
+        layout.views().iter().map(|view| view.buffer_idx).collect()
+    }
+
+    // Two views side by side, the second of which is split into one above the other.
+    fn three_views() -> Layout {
+        let mut layout = Layout::View(View::new(0));
+        layout.split(0, SplitDirection::Vertical);
+        layout.split(1, SplitDirection::Horizontal);
+        layout.view_mut(1).buffer_idx = 1;
+        layout.view_mut(2).buffer_idx = 2;
+
+        layout
+    }
+
+    #[test]
+    fn split_views_come_after_the_original() {
+        let mut layout = three_views();
+        assert_eq!(buffer_idxs(&layout), [0, 1, 2]);
+
+        layout.split(1, SplitDirection::Vertical);
+        assert_eq!(buffer_idxs(&layout), [0, 1, 1, 2]);
+        assert_eq!(layout.len(), 4);
+    }
+
+    #[test]
+    fn closing_gives_the_space_to_the_other_half() {
+        let layout = three_views().close(1).unwrap();
+        assert_eq!(buffer_idxs(&layout), [0, 2]);
+
+        let layout = layout.close(0).unwrap();
+        assert_eq!(buffer_idxs(&layout), [2]);
+        assert!(layout.close(0).is_none());
+    }
+
+    #[test]
+    fn space_is_shared_evenly_with_separators_between_columns() {
+        let area = Rect {
+            x: 0,
+            y: 0,
+            cols: 81,
+            lines: 25,
+        };
+        let (rects, separators) = three_views().arrange(area);
+
+        assert_eq!(
+            rects,
+            [
+                Rect {
+                    x: 0,
+                    y: 0,
+                    cols: 40,
+                    lines: 25
+                },
+                Rect {
+                    x: 41,
+                    y: 0,
+                    cols: 40,
+                    lines: 13
+                },
+                Rect {
+                    x: 41,
+                    y: 13,
+                    cols: 40,
+                    lines: 12
+                },
+            ]
+        );
+        assert_eq!(
+            separators,
+            [Rect {
+                x: 40,
+                y: 0,
+                cols: 1,
+                lines: 25
+            }]
+        );
+    }
+
+    #[test]
+    fn tiny_areas_dont_underflow() {
+        let mut layout = Layout::View(View::new(0));
+        layout.split(0, SplitDirection::Vertical);
+        layout.split(0, SplitDirection::Horizontal);
+
+        let (rects, _) = layout.arrange(Rect {
+            x: 0,
+            y: 0,
+            cols: 0,
+            lines: 1,
+        });
+
+        assert!(rects.iter().all(|rect| rect.cols == 0 && rect.lines <= 1));
+    }
+}
@@ -2,55 +2,79 @@ use {std::path::PathBuf, structopt::StructOpt};
 
 #[derive(StructOpt)]
 struct Opts {
-    path: PathBuf,
+    /// The files to open
+    #[structopt(required = true)]
+    paths: Vec<PathBuf>,
+    /// The syntax highlighting theme to use, either built-in or defined in the config directory
+    #[structopt(long, default_value = "gruvbox")]
+    theme: String,
+    /// How to number lines in the gutter: `off`, `absolute` or `relative`
+    #[structopt(long, default_value = "absolute")]
+    line_numbers: se::LineNumbers,
 }
 
 fn main() -> anyhow::Result<()> {
     use {
-        crossterm::{
-            event::{self, KeyCode, KeyModifiers},
-            queue, terminal,
-        },
-        std::io::Write,
+        crossterm::{event, queue, terminal},
+        std::{io::Write, time::Duration},
     };
 
     let opts = Opts::from_args();
 
-    // Attempt to load the given file before doing anything else.
-    let mut buffer = se::Buffer::new(opts.path)?;
+    // Attempt to load the given files before doing anything else.
+    let mut editor = se::Editor::new(&opts.paths, &opts.theme, opts.line_numbers)?;
 
     let mut stdout = std::io::stdout();
 
-    queue!(stdout, terminal::EnterAlternateScreen)?;
+    queue!(
+        stdout,
+        terminal::EnterAlternateScreen,
+        event::EnableMouseCapture,
+        event::EnableBracketedPaste
+    )?;
     terminal::enable_raw_mode()?;
 
-    buffer.initialize_terminal(&mut stdout)?;
-    buffer.redraw(&mut stdout)?;
-
-    loop {
-        if let event::Event::Key(k) = event::read()? {
-            match (k.code, k.modifiers) {
-                (c, KeyModifiers::NONE) => match c {
-                    KeyCode::Up => buffer.move_cursor(se::Direction::Up),
-                    KeyCode::Down => buffer.move_cursor(se::Direction::Down),
-                    KeyCode::Left => buffer.move_cursor(se::Direction::Left),
-                    KeyCode::Right => buffer.move_cursor(se::Direction::Right),
-                    KeyCode::Backspace => buffer.backspace(),
-                    KeyCode::Enter => buffer.insert_newline(),
-                    KeyCode::Char(c) => buffer.insert_char(c),
-                    _ => (),
-                },
-                // Quit on C-q
-                (KeyCode::Char('q'), KeyModifiers::CONTROL) => break,
-                _ => (),
+    editor.initialize_terminal(&mut stdout)?;
+    editor.redraw(&mut stdout)?;
+
+    while editor.is_running() {
+        // Everything that has already arrived is handled before redrawing, so that e.g. keys typed
+        // faster than the screen can be redrawn don’t each cause a redraw.
+        let mut is_resized = false;
+
+        loop {
+            match event::read()? {
+                // Some terminals report keys being released as well as pressed.
+                event::Event::Key(k) if k.kind != event::KeyEventKind::Release => {
+                    editor.handle_key(k)
+                }
+                event::Event::Paste(text) => editor.handle_paste(&text),
+                event::Event::Mouse(m) => editor.handle_mouse(m),
+                event::Event::Resize(_, _) => is_resized = true,
+                _ => {}
+            }
+
+            if !event::poll(Duration::from_millis(0))? {
+                break;
             }
         }
 
-        buffer.redraw(&mut stdout)?;
+        // Whatever was on the screen before it was resized has been moved around by the terminal,
+        // so it has to be cleared before the views are drawn in their new sizes.
+        if is_resized {
+            editor.initialize_terminal(&mut stdout)?;
+        }
+
+        editor.redraw(&mut stdout)?;
     }
 
     terminal::disable_raw_mode()?;
-    queue!(stdout, terminal::LeaveAlternateScreen)?;
+    queue!(
+        stdout,
+        event::DisableBracketedPaste,
+        event::DisableMouseCapture,
+        terminal::LeaveAlternateScreen
+    )?;
     stdout.flush()?;
 
     Ok(())
@@ -0,0 +1,119 @@
+use {
+    crate::graphemes,
+    crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
+    unicode_segmentation::UnicodeSegmentation,
+};
+
+// A single line of input the user is asked for on the message line, such as the path of a file to
+// open. What is done with the input is up to whoever opened the prompt.
+#[derive(Debug)]
+pub(crate) struct Prompt {
+    label: String,
+    input: String,
+    // The completions being cycled through with tab, and which of them is in the input.
+    completions: Vec<String>,
+    completion_idx: usize,
+}
+
+#[derive(Debug)]
+pub(crate) enum PromptEvent {
+    Edited,
+    Submitted(String),
+    Cancelled,
+    // The user pressed tab to complete the input, and it’s up to whoever opened the prompt to
+    // come up with completions for it.
+    CompletionRequested,
+    Ignored,
+}
+
+impl Prompt {
+    pub(crate) fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            input: String::new(),
+            completions: Vec::new(),
+            completion_idx: 0,
+        }
+    }
+
+    // Puts the first of the given completions into the input. If there are several then tab and
+    // shift-tab cycle through them, until the input is edited.
+    pub(crate) fn set_completions(&mut self, completions: Vec<String>) {
+        if let Some(completion) = completions.first() {
+            self.input = completion.clone();
+        }
+
+        // With only one completion there is nothing to cycle through, so the next tab completes
+        // whatever has been completed so far even further (e.g. the contents of a directory).
+        self.completions = if completions.len() > 1 {
+            completions
+        } else {
+            Vec::new()
+        };
+        self.completion_idx = 0;
+    }
+
+    pub(crate) fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub(crate) fn handle_key(&mut self, key: KeyEvent) -> PromptEvent {
+        match (key.code, key.modifiers) {
+            (KeyCode::Tab, _) if self.completions.is_empty() => PromptEvent::CompletionRequested,
+            (KeyCode::Tab, _) => {
+                self.cycle_completions(1);
+                PromptEvent::Edited
+            }
+            (KeyCode::BackTab, _) if !self.completions.is_empty() => {
+                self.cycle_completions(self.completions.len() - 1);
+                PromptEvent::Edited
+            }
+
+            (KeyCode::Enter, _) => PromptEvent::Submitted(std::mem::take(&mut self.input)),
+
+            // Escape and C-g both back out of the prompt.
+            (KeyCode::Esc, _) | (KeyCode::Char('g'), KeyModifiers::CONTROL) => {
+                PromptEvent::Cancelled
+            }
+
+            (KeyCode::Backspace, _) => {
+                self.completions.clear();
+
+                if let Some((idx, _)) = self.input.grapheme_indices(true).next_back() {
+                    self.input.truncate(idx);
+                }
+
+                PromptEvent::Edited
+            }
+
+            (KeyCode::Char(c), KeyModifiers::NONE) | (KeyCode::Char(c), KeyModifiers::SHIFT) => {
+                self.completions.clear();
+                self.input.push(c);
+                PromptEvent::Edited
+            }
+
+            _ => PromptEvent::Ignored,
+        }
+    }
+
+    fn cycle_completions(&mut self, step: usize) {
+        self.completion_idx = (self.completion_idx + step) % self.completions.len();
+        self.input = self.completions[self.completion_idx].clone();
+    }
+
+    // The prompt as it is displayed, cut down to the given width, and the column of the cursor
+    // within it. If the input is too long to fit then its start is cut off, so that the part being
+    // typed stays visible.
+    pub(crate) fn display(&self, width_in_cols: usize) -> (String, usize) {
+        let label_width = graphemes::display_width(&self.label);
+        let input_width = graphemes::display_width(&self.input);
+
+        // Leave a column free for the cursor at the end of the input.
+        let hidden_cols = (label_width + input_width + 1).saturating_sub(width_in_cols);
+
+        let displayed = format!("{}{}", self.label, self.input);
+        let displayed = graphemes::clip(&displayed, hidden_cols, width_in_cols);
+
+        (displayed, label_width + input_width - hidden_cols)
+    }
+}
@@ -0,0 +1,84 @@
+use crate::clipboard::Clipboard;
+
+// Where yanked and deleted text is kept until it is put back into a buffer. Text taken from whole
+// lines is put back as whole lines, no matter where on the line the cursor is.
+//
+// If the system clipboard is used then text is copied to it as well, and whatever is on it when
+// pasting takes the place of the register’s own text. Should the clipboard fail, the register
+// carries on with its own text, and the error is kept for the editor to report.
+#[derive(Debug, Default)]
+pub(crate) struct Register {
+    text: String,
+    is_linewise: bool,
+    clipboard: Clipboard,
+    error: Option<anyhow::Error>,
+}
+
+impl Register {
+    pub(crate) fn new(clipboard: Clipboard) -> Self {
+        Self {
+            clipboard,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn set(&mut self, text: String, is_linewise: bool) {
+        // Other programs expect lines to end with a line feed.
+        let result = if is_linewise {
+            self.clipboard.copy(&format!("{}\n", text))
+        } else {
+            self.clipboard.copy(&text)
+        };
+
+        self.text = text;
+        self.is_linewise = is_linewise;
+        self.error = result.err();
+    }
+
+    // The text to paste, and whether it is made up of whole lines. If the clipboard has been
+    // changed by another program then its text is treated as whole lines if it ends in a line
+    // feed, as Vim does.
+    pub(crate) fn get(&mut self) -> (&str, bool) {
+        match self.clipboard.paste() {
+            Ok(Some(text)) => {
+                let text = text.replace("\r\n", "\n");
+                let own_text = if self.is_linewise {
+                    format!("{}\n", self.text)
+                } else {
+                    self.text.clone()
+                };
+
+                if text != own_text {
+                    self.is_linewise = text.ends_with('\n');
+                    self.text = text;
+
+                    if self.is_linewise {
+                        self.text.pop();
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(e) => self.error = Some(e),
+        }
+
+        (&self.text, self.is_linewise)
+    }
+
+    // The text to paste at the cursor, with whole lines ending in a line feed. Unlike `get`, this
+    // is exactly what is on the clipboard, however it ends.
+    pub(crate) fn text(&mut self) -> String {
+        match self.get() {
+            (text, true) => format!("{}\n", text),
+            (text, false) => text.to_string(),
+        }
+    }
+
+    pub(crate) fn take_error(&mut self) -> Option<String> {
+        self.error.take().map(|e| {
+            format!(
+                "Clipboard unavailable, using the internal register: {:#}",
+                e
+            )
+        })
+    }
+}
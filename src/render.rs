@@ -0,0 +1,417 @@
+use {
+    crate::{graphemes, text::Text},
+    ansi_term::Style,
+    std::ops::Range,
+};
+
+// A piece of text on a single line which is displayed in a single style. Keeping text and styles
+// apart until the very last moment means that we can work out what is visible on screen without
+// having to skip over escape sequences.
+#[derive(Debug)]
+pub(crate) struct Span {
+    pub(crate) text: String,
+    pub(crate) style: Style,
+}
+
+pub(crate) type Line = Vec<Span>;
+
+// Highlighting is by far the most expensive part of redrawing, so rather than highlighting the
+// whole buffer on every key press we only highlight as far as the bottom of the screen, and cache
+// the results line by line. Edits invalidate the cache from the edited line onwards.
+#[derive(Debug, Default)]
+pub(crate) struct Highlights {
+    lines: Vec<Line>,
+    // Whether highlighting can be restarted at each line without knowing about the lines before
+    // it. See `highlight` for when this is the case.
+    restart_points: Vec<bool>,
+}
+
+impl Highlights {
+    pub(crate) fn invalidate_from(&mut self, line_nr: usize) {
+        self.lines.truncate(line_nr);
+        self.restart_points.truncate(line_nr);
+    }
+
+    // Gets the highlighted versions of the given lines, highlighting whatever isn’t already cached.
+    pub(crate) fn get(
+        &mut self,
+        text: &Text,
+        range: Range<usize>,
+        highlighter: &dyn dialect::Highlight,
+        theme: &dyn dialect::Theme,
+    ) -> &[Line] {
+        let end = range.end.min(text.len_lines());
+
+        if self.lines.len() < end {
+            // Highlighting has to start at a restart point, which means going back a little
+            // before the first uncached line. The first line is always a restart point.
+            let start = self
+                .restart_points
+                .iter()
+                .rposition(|is_restart_point| *is_restart_point)
+                .unwrap_or(0);
+
+            self.invalidate_from(start);
+
+            for (line, is_restart_point) in highlight(&text.lines(start..end), highlighter, theme) {
+                self.lines.push(line);
+                self.restart_points.push(is_restart_point);
+            }
+
+            // The first line of the file is a restart point even if it starts with whitespace.
+            if start == 0 {
+                self.restart_points[0] = true;
+            }
+        }
+
+        &self.lines[range.start.min(end)..end]
+    }
+}
+
+// This function highlights the input with the given highlighter, renders this with a given theme,
+// and splits the resulting spans into lines.
+//
+// Each line is also marked with whether it is a restart point. Highlighters only see the text
+// they are given, so for their output to be the same as if they had seen the whole file the text
+// has to start at the top level of the file’s syntax. We approximate this with lines that start
+// at the first column (since nested code is indented), and that don’t start in the middle of a
+// span that runs over several lines (such as a block comment).
+fn highlight(
+    input: &str,
+    highlighter: &dyn dialect::Highlight,
+    theme: &dyn dialect::Theme,
+) -> Vec<(Line, bool)> {
+    let default_style = theme.default_style();
+    let mut lines = vec![(Vec::new(), false)];
+
+    for dialect::HighlightedSpan { text, group } in highlighter.highlight(input) {
+        // Spans without a highlight group just use the theme’s default style.
+        let style: Style = group
+            .map_or(default_style, |group| {
+                resolve(theme.style(group), default_style)
+            })
+            .into();
+
+        // Spans can run over several lines (think of block comments), in which case every line
+        // break within them starts a new line.
+        for (idx, text) in text.split('\n').enumerate() {
+            if idx > 0 {
+                lines.push((Vec::new(), false));
+            }
+
+            if text.is_empty() {
+                continue;
+            }
+
+            let (line, is_restart_point) = lines.last_mut().unwrap();
+
+            // A line starting partway through a span is only a restart point if that span is
+            // unhighlighted, since then it’s just whitespace or plain text.
+            if line.is_empty() {
+                *is_restart_point =
+                    (idx == 0 || group.is_none()) && !text.starts_with(char::is_whitespace);
+            }
+
+            line.push(Span {
+                text: text.to_string(),
+                style,
+            });
+        }
+    }
+
+    lines
+}
+
+// Fills in the colors a style leaves unspecified with those of the theme’s default style. This
+// mirrors what `dialect::render` does, which we can’t use directly since it needs to own the theme.
+fn resolve(style: dialect::Style, default_style: dialect::ResolvedStyle) -> dialect::ResolvedStyle {
+    dialect::ResolvedStyle {
+        fg_color: style.fg_color.unwrap_or(default_style.fg_color),
+        bg_color: style.bg_color.unwrap_or(default_style.bg_color),
+        is_bold: style.is_bold,
+        is_italic: style.is_italic,
+        is_underline: style.is_underline,
+    }
+}
+
+// Restyles the parts of a line that fall within the given byte ranges (such as search matches),
+// keeping the rest of their highlighting. The ranges must be sorted and must not overlap.
+pub(crate) fn overlay(
+    line: &[Span],
+    ranges: &[Range<usize>],
+    restyle: impl Fn(Style) -> Style,
+) -> Line {
+    let mut overlaid = Vec::new();
+    let mut span_start = 0;
+
+    for span in line {
+        let span_end = span_start + span.text.len();
+        let mut idx = span_start;
+
+        // Split the span at the edges of each range that overlaps it.
+        for range in ranges
+            .iter()
+            .filter(|range| range.start < span_end && range.end > span_start)
+        {
+            let start = range.start.max(span_start);
+            let end = range.end.min(span_end);
+
+            if idx < start {
+                overlaid.push(Span {
+                    text: span.text[idx - span_start..start - span_start].to_string(),
+                    style: span.style,
+                });
+            }
+
+            overlaid.push(Span {
+                text: span.text[start - span_start..end - span_start].to_string(),
+                style: restyle(span.style),
+            });
+
+            idx = end;
+        }
+
+        if idx < span_end {
+            overlaid.push(Span {
+                text: span.text[idx - span_start..].to_string(),
+                style: span.style,
+            });
+        }
+
+        span_start = span_end;
+    }
+
+    overlaid
+}
+
+// Replaces the tabs and other control characters in a line with what is displayed in their place,
+// which has to come after overlaying anything on the line since it changes byte offsets.
+pub(crate) fn expand(line: &[Span]) -> Line {
+    let mut col = 0;
+
+    line.iter()
+        .map(|span| {
+            let text = graphemes::expand(&span.text, col).into_owned();
+            col += graphemes::display_width(&text);
+
+            Span {
+                text,
+                style: span.style,
+            }
+        })
+        .collect()
+}
+
+// Cuts out the part of a line that is displayed between the given terminal columns. The line
+// must already have been expanded.
+pub(crate) fn clip(line: &[Span], start_col: usize, width_in_cols: usize) -> Line {
+    let end_col = start_col + width_in_cols;
+    let mut clipped = Vec::new();
+    let mut col = 0;
+
+    for span in line {
+        if col >= end_col {
+            break;
+        }
+
+        let span_width = graphemes::display_width(&span.text);
+
+        // Only spans that are at least partially on screen are kept.
+        if col + span_width > start_col {
+            let visible_start = start_col.max(col);
+
+            clipped.push(Span {
+                text: graphemes::clip(&span.text, visible_start - col, end_col - visible_start),
+                style: span.style,
+            });
+        }
+
+        col += span_width;
+    }
+
+    clipped
+}
+
+// Adds escape sequences to switch between the theme’s colors, and collects the line into a string.
+pub(crate) fn to_ansi(line: &[Span], default_style: Style) -> String {
+    line.iter()
+        .map(|span| {
+            format!(
+                "{}{}{}",
+                // Transition from the theme’s default style to the style of the current span.
+                default_style.infix(span.style),
+                // Include the span’s text.
+                span.text,
+                // Transition back to the default style.
+                span.style.infix(default_style)
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::themes::{Theme, Themes},
+        dialect::{HighlightGroup, HighlightedSpan},
+        std::cell::RefCell,
+    };
+
+    // Highlights `"…"` as a string and `/* … */` as a comment, either of which can run over
+    // several lines, and remembers all the input it is given.
+    #[derive(Default)]
+    struct Highlighter {
+        inputs: RefCell<Vec<String>>,
+    }
+
+    impl dialect::Highlight for Highlighter {
+        fn highlight<'input>(&self, input: &'input str) -> Vec<HighlightedSpan<'input>> {
+            self.inputs.borrow_mut().push(input.to_string());
+
+            let mut spans = Vec::new();
+            let mut rest = input;
+
+            while !rest.is_empty() {
+                let (len, group) = if let Some(string) = rest.strip_prefix('"') {
+                    let len = string.find('"').map_or(rest.len(), |idx| idx + 2);
+                    (len, Some(HighlightGroup::String))
+                } else if rest.starts_with("/*") {
+                    let len = rest.find("*/").map_or(rest.len(), |idx| idx + 2);
+                    (len, Some(HighlightGroup::Comment))
+                } else {
+                    let len = rest
+                        .char_indices()
+                        .skip(1)
+                        .map(|(idx, _)| idx)
+                        .find(|idx| rest[*idx..].starts_with(['"', '/']))
+                        .unwrap_or(rest.len());
+                    (len, None)
+                };
+
+                spans.push(HighlightedSpan {
+                    text: &rest[..len],
+                    group,
+                });
+                rest = &rest[len..];
+            }
+
+            spans
+        }
+    }
+
+    fn theme() -> Theme {
+        Themes::default().get("gruvbox").unwrap()
+    }
+
+    fn texts(line: &[Span]) -> Vec<&str> {
+        line.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    #[test]
+    fn edits_are_highlighted_again_from_the_nearest_restart_point() {
+        let (highlighter, theme) = (Highlighter::default(), theme());
+        let mut highlights = Highlights::default();
+
+        let text = Text::from_file_contents("fn a() {\n    1\n}\nfn b() {\n    2\n}");
+        highlights.get(&text, 0..6, &highlighter, &theme);
+        assert_eq!(
+            highlights.restart_points,
+            vec![true, false, true, true, false, true]
+        );
+
+        // Editing the indented line after `fn b` goes back to the line with `fn b` on it.
+        let text = Text::from_file_contents("fn a() {\n    1\n}\nfn b() {\n    3\n}");
+        highlights.invalidate_from(4);
+        let lines = highlights.get(&text, 4..5, &highlighter, &theme);
+        assert_eq!(texts(&lines[0]), vec!["    3"]);
+        assert_eq!(highlighter.inputs.borrow().len(), 2);
+        assert!(highlighter.inputs.borrow()[1].starts_with("fn b() {\n"));
+        assert_eq!(highlights.lines.len(), 5);
+    }
+
+    #[test]
+    fn lines_inside_multi_line_strings_and_comments_are_not_restart_points() {
+        let (highlighter, theme) = (Highlighter::default(), theme());
+        let mut highlights = Highlights::default();
+
+        let text = Text::from_file_contents("a\n/* one\ntwo */\n\"three\nfour\"\nb");
+        highlights.get(&text, 0..6, &highlighter, &theme);
+        assert_eq!(
+            highlights.restart_points,
+            vec![true, true, false, true, false, true]
+        );
+
+        // Starting from the middle of the string would see its end as the start of another.
+        highlights.invalidate_from(5);
+        let lines = highlights.get(&text, 3..6, &highlighter, &theme);
+        assert_eq!(lines[0][0].style, lines[1][0].style);
+        assert!(highlighter.inputs.borrow()[1].starts_with("\"three\n"));
+
+        highlights.invalidate_from(3);
+        highlights.get(&text, 0..6, &highlighter, &theme);
+        assert!(highlighter.inputs.borrow()[2].starts_with("/* one\n"));
+    }
+
+    fn line(texts: &[&str]) -> Line {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(idx, text)| Span {
+                text: text.to_string(),
+                style: Style::new().fg(ansi_term::Colour::Fixed(idx as u8)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clipping_keeps_the_spans_between_the_columns() {
+        let line = line(&["abc", "def", "ghi"]);
+
+        assert_eq!(texts(&clip(&line, 0, 9)), vec!["abc", "def", "ghi"]);
+        assert_eq!(texts(&clip(&line, 2, 5)), vec!["c", "def", "g"]);
+        assert_eq!(texts(&clip(&line, 3, 3)), vec!["def"]);
+        assert_eq!(texts(&clip(&line, 8, 10)), vec!["i"]);
+        assert!(clip(&line, 9, 10).is_empty());
+
+        // The spans keep their styles.
+        assert_eq!(clip(&line, 2, 5)[1].style, line[1].style);
+    }
+
+    #[test]
+    fn wide_graphemes_cut_by_clipping_become_spaces() {
+        let line = line(&["a漢", "字b"]);
+
+        assert_eq!(texts(&clip(&line, 0, 6)), vec!["a漢", "字b"]);
+        assert_eq!(texts(&clip(&line, 2, 4)), vec![" ", "字b"]);
+        assert_eq!(texts(&clip(&line, 0, 4)), vec!["a漢", " "]);
+    }
+
+    #[test]
+    fn tabs_are_clipped_once_expanded() {
+        let line = expand(&line(&["a\t", "\tb"]));
+        assert_eq!(texts(&line), vec!["a   ", "    b"]);
+
+        assert_eq!(texts(&clip(&line, 2, 4)), vec!["  ", "  "]);
+        assert_eq!(texts(&clip(&line, 8, 1)), vec!["b"]);
+    }
+
+    #[test]
+    fn overlays_split_spans_at_the_edges_of_ranges() {
+        let line = line(&["abc", "def"]);
+        let bold = |style: Style| style.bold();
+
+        let overlaid = overlay(&line, &[1..2, 2..4], bold);
+        assert_eq!(texts(&overlaid), vec!["a", "b", "c", "d", "ef"]);
+        assert_eq!(overlaid[0].style, line[0].style);
+        assert_eq!(overlaid[1].style, line[0].style.bold());
+        assert_eq!(overlaid[3].style, line[1].style.bold());
+        assert_eq!(overlaid[4].style, line[1].style);
+
+        assert_eq!(texts(&overlay(&line, &[], bold)), vec!["abc", "def"]);
+        assert_eq!(
+            texts(&overlay(&line, &[0..3, 3..6], bold)),
+            vec!["abc", "def"]
+        );
+    }
+}
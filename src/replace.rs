@@ -0,0 +1,270 @@
+use {crate::buffer::Buffer, regex::Regex, std::ops::Range};
+
+// An ongoing find-and-replace, which steps through the matches of a regular expression in part of
+// a buffer, replacing or skipping each one. Every replacement made is part of one undo step.
+//
+// Byte offsets into the buffer are used throughout, since matches can span several lines.
+#[derive(Debug)]
+pub(crate) struct Replace {
+    regex: Regex,
+    replacement: String,
+    // Where the next match is searched for from.
+    from: usize,
+    // Where the region being replaced in ends, counted back from the end of the buffer so that
+    // replacing text inside the region doesn’t move it.
+    end_from_back: usize,
+    current_match: Option<Range<usize>>,
+    count: usize,
+}
+
+impl Replace {
+    // Replacement text can refer to capture groups as `$1` or `${name}`.
+    pub(crate) fn new(
+        regex: Regex,
+        replacement: String,
+        region: Range<usize>,
+        buffer: &mut Buffer,
+    ) -> Self {
+        buffer.begin_edit_group();
+
+        let mut replace = Self {
+            regex,
+            replacement,
+            from: region.start,
+            end_from_back: buffer.len_bytes() - region.end,
+            current_match: None,
+            count: 0,
+        };
+        replace.find_next(buffer);
+
+        replace
+    }
+
+    pub(crate) fn regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    pub(crate) fn replacement(&self) -> &str {
+        &self.replacement
+    }
+
+    // Whether there is a match waiting to be replaced or skipped. Once there isn’t the
+    // find-and-replace is over.
+    pub(crate) fn has_match(&self) -> bool {
+        self.current_match.is_some()
+    }
+
+    pub(crate) fn replace_current(&mut self, buffer: &mut Buffer) {
+        let contents = buffer.contents();
+
+        if let Some(range) = self.current_match.take() {
+            let replacement = self.expand(&contents, range.start);
+            buffer.replace(range.clone(), &replacement);
+            self.count += 1;
+
+            self.from = range.start + replacement.len();
+            self.skip_empty_match(&range, &contents);
+            self.find_next(buffer);
+        }
+    }
+
+    pub(crate) fn skip_current(&mut self, buffer: &mut Buffer) {
+        if let Some(range) = self.current_match.take() {
+            self.from = range.end;
+            self.skip_empty_match(&range, &buffer.contents());
+            self.find_next(buffer);
+        }
+    }
+
+    // Replaces every remaining match in one go. The matches are the ones stepping through them
+    // would find, and are replaced from last to first so that each replacement leaves the offsets
+    // of the ones before it alone.
+    pub(crate) fn replace_all(&mut self, buffer: &mut Buffer) {
+        let contents = buffer.contents();
+        let end = self.end(buffer);
+        let mut replacements = Vec::new();
+
+        while self.from <= end {
+            let (range, replacement) = match self.regex.captures_at(&contents, self.from) {
+                Some(captures) => {
+                    let mut replacement = String::new();
+                    captures.expand(&self.replacement, &mut replacement);
+
+                    (captures.get(0).unwrap().range(), replacement)
+                }
+                None => break,
+            };
+
+            if range.end > end {
+                break;
+            }
+
+            self.from = range.end;
+            self.skip_empty_match(&range, &contents);
+            replacements.push((range, replacement));
+        }
+
+        for (range, replacement) in replacements.iter().rev() {
+            buffer.replace(range.clone(), replacement);
+        }
+
+        // Leave the cursor after the last replacement, as if they had been made one by one. Every
+        // replacement before the last one moves it by the difference in length.
+        if let Some(((last_range, last_replacement), earlier)) = replacements.split_last() {
+            let removed: usize = earlier.iter().map(|(range, _)| range.len()).sum();
+            let inserted: usize = earlier
+                .iter()
+                .map(|(_, replacement)| replacement.len())
+                .sum();
+
+            buffer.move_cursor_to_byte(
+                last_range.start - removed + inserted + last_replacement.len(),
+            );
+        }
+
+        self.count += replacements.len();
+        self.current_match = None;
+    }
+
+    // Ends the find-and-replace, returning how many replacements were made.
+    pub(crate) fn finish(self, buffer: &mut Buffer) -> usize {
+        buffer.end_edit_group();
+        self.count
+    }
+
+    fn find_next(&mut self, buffer: &mut Buffer) {
+        let contents = buffer.contents();
+        let end = self.end(buffer);
+
+        self.current_match = if self.from <= end {
+            self.regex
+                .find_at(&contents, self.from)
+                .map(|m| m.range())
+                .filter(|range| range.end <= end)
+        } else {
+            None
+        };
+
+        if let Some(range) = &self.current_match {
+            buffer.move_cursor_to_byte(range.start);
+        }
+    }
+
+    // An empty match would be found again at the same place, so we move past the character after
+    // it instead.
+    fn skip_empty_match(&mut self, range: &Range<usize>, contents: &str) {
+        if range.is_empty() {
+            match contents[range.start..].chars().next() {
+                Some(c) => self.from += c.len_utf8(),
+                None => self.from = usize::MAX,
+            }
+        }
+    }
+
+    fn end(&self, buffer: &Buffer) -> usize {
+        buffer.len_bytes() - self.end_from_back
+    }
+
+    fn expand(&self, contents: &str, start: usize) -> String {
+        let mut replacement = String::new();
+
+        if let Some(captures) = self.regex.captures_at(contents, start) {
+            captures.expand(&self.replacement, &mut replacement);
+        }
+
+        replacement
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(contents: &str) -> Buffer {
+        let mut buffer = Buffer::new("test.txt").unwrap();
+        buffer.replace(0..0, contents);
+        buffer.move_cursor_to(0, 0);
+
+        buffer
+    }
+
+    // Replaces every match in the region straight away, returning how many there were.
+    fn replace_all(
+        buffer: &mut Buffer,
+        regex: &str,
+        replacement: &str,
+        region: Range<usize>,
+    ) -> usize {
+        let regex = Regex::new(regex).unwrap();
+        let mut replace = Replace::new(regex, replacement.to_string(), region, buffer);
+        replace.replace_all(buffer);
+
+        replace.finish(buffer)
+    }
+
+    #[test]
+    fn replacements_expand_capture_groups() {
+        let mut buffer = buffer("let a = b;\nlet c = d;");
+        let len = buffer.len_bytes();
+
+        let count = replace_all(&mut buffer, r"(\w) = (\w)", "$2 = $1", 0..len);
+        assert_eq!(count, 2);
+        assert_eq!(buffer.contents(), "let b = a;\nlet d = c;");
+    }
+
+    #[test]
+    fn empty_matches_are_replaced_once_each() {
+        let mut buffer = buffer("aéb");
+        let len = buffer.len_bytes();
+
+        let count = replace_all(&mut buffer, "x*", "-", 0..len);
+        assert_eq!(count, 4);
+        assert_eq!(buffer.contents(), "-a-é-b-");
+    }
+
+    #[test]
+    fn replacing_all_leaves_the_cursor_after_the_last_replacement() {
+        let mut buffer = buffer("one two one two");
+        let len = buffer.len_bytes();
+
+        replace_all(&mut buffer, "one", "three", 0..len);
+        assert_eq!(buffer.contents(), "three two three two");
+        assert_eq!(buffer.cursor_byte_idx(), 15);
+    }
+
+    #[test]
+    fn only_matches_inside_the_region_are_replaced() {
+        let mut buffer = buffer("a a a a");
+
+        let count = replace_all(&mut buffer, "a", "b", 2..5);
+        assert_eq!(count, 2);
+        assert_eq!(buffer.contents(), "a b b a");
+    }
+
+    #[test]
+    fn matches_can_start_inside_a_match_before_the_region() {
+        let mut buffer = buffer("aaa");
+        let regex = Regex::new("aa").unwrap();
+        let mut replace = Replace::new(regex, "b".to_string(), 1..3, &mut buffer);
+        assert_eq!(replace.current_match, Some(1..3));
+
+        replace.replace_all(&mut buffer);
+        assert_eq!(replace.finish(&mut buffer), 1);
+        assert_eq!(buffer.contents(), "ab");
+    }
+
+    #[test]
+    fn stepping_through_matches_replaces_or_skips_each() {
+        let mut buffer = buffer("a a a");
+        let regex = Regex::new("a").unwrap();
+        let len = buffer.len_bytes();
+        let mut replace = Replace::new(regex, "bb".to_string(), 0..len, &mut buffer);
+
+        replace.replace_current(&mut buffer);
+        replace.skip_current(&mut buffer);
+        replace.replace_all(&mut buffer);
+        assert!(!replace.has_match());
+        assert_eq!(replace.finish(&mut buffer), 2);
+        assert_eq!(buffer.contents(), "bb a bb");
+    }
+}
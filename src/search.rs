@@ -0,0 +1,204 @@
+use {
+    crate::text::{Position, Text},
+    regex::Regex,
+    std::ops::Range,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+// Finds the nearest match of the query in the given direction from a position, which itself only
+// counts if `include_from` is set. If there are no more matches in that direction then the search
+// wraps around to the other end of the text, which is reported alongside the match’s position.
+//
+// Matches are exact, and never span more than one line.
+pub(crate) fn find(
+    text: &Text,
+    query: &str,
+    from: Position,
+    direction: SearchDirection,
+    include_from: bool,
+) -> Option<(Position, bool)> {
+    if query.is_empty() {
+        return None;
+    }
+
+    let len_lines = text.len_lines();
+
+    // Every line is searched once, and then the line we started on is searched again for the
+    // matches on the other side of the starting position.
+    for offset in 0..=len_lines {
+        let (line_nr, is_wrapped) = match direction {
+            SearchDirection::Forward => {
+                let line_nr = from.line + offset;
+                (line_nr % len_lines, line_nr >= len_lines)
+            }
+            SearchDirection::Backward => (
+                (from.line + len_lines - offset % len_lines) % len_lines,
+                offset > from.line,
+            ),
+        };
+
+        let line = text.line(line_nr);
+        let mut starts = match_starts(&line, query);
+
+        let col = match direction {
+            SearchDirection::Forward if offset == 0 => {
+                starts.find(|col| *col > from.col || include_from && *col == from.col)
+            }
+            SearchDirection::Forward if offset == len_lines => starts.find(|col| *col <= from.col),
+            SearchDirection::Forward => starts.next(),
+            SearchDirection::Backward if offset == 0 => starts
+                .filter(|col| *col < from.col || include_from && *col == from.col)
+                .last(),
+            SearchDirection::Backward if offset == len_lines => {
+                starts.filter(|col| *col >= from.col).last()
+            }
+            SearchDirection::Backward => starts.last(),
+        };
+
+        if let Some(col) = col {
+            return Some((Position { line: line_nr, col }, is_wrapped));
+        }
+    }
+
+    None
+}
+
+// The byte ranges of the matches of a regular expression in a line, for highlighting them. Empty
+// matches are left out, since there would be nothing to highlight.
+pub(crate) fn matches_in_line(line: &str, regex: &Regex) -> Vec<Range<usize>> {
+    regex
+        .find_iter(line)
+        .map(|m| m.range())
+        .filter(|range| !range.is_empty())
+        .collect()
+}
+
+// A regular expression that matches the query literally, so that plain searches can be
+// highlighted in the same way as regular expressions.
+pub(crate) fn literal_regex(query: &str) -> Option<Regex> {
+    if query.is_empty() {
+        return None;
+    }
+
+    Regex::new(&regex::escape(query)).ok()
+}
+
+// Every byte index in the line at which a match starts, including those of matches that overlap.
+fn match_starts<'a>(line: &'a str, query: &'a str) -> impl Iterator<Item = usize> + 'a {
+    line.char_indices()
+        .map(|(idx, _)| idx)
+        .filter(move |idx| line[*idx..].starts_with(query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: usize, col: usize) -> Position {
+        Position { line, col }
+    }
+
+    fn find_in(
+        contents: &str,
+        from: Position,
+        direction: SearchDirection,
+        include_from: bool,
+    ) -> Option<(Position, bool)> {
+        find(
+            &Text::from_file_contents(contents),
+            "ab",
+            from,
+            direction,
+            include_from,
+        )
+    }
+
+    #[test]
+    fn forward_finds_the_next_match() {
+        let contents = "ab ab\nx\nab";
+
+        assert_eq!(
+            find_in(contents, at(0, 0), SearchDirection::Forward, true),
+            Some((at(0, 0), false))
+        );
+        assert_eq!(
+            find_in(contents, at(0, 0), SearchDirection::Forward, false),
+            Some((at(0, 3), false))
+        );
+        assert_eq!(
+            find_in(contents, at(0, 3), SearchDirection::Forward, false),
+            Some((at(2, 0), false))
+        );
+    }
+
+    #[test]
+    fn forward_wraps_around_to_the_top() {
+        let contents = "ab ab\nx\nab";
+
+        assert_eq!(
+            find_in(contents, at(2, 0), SearchDirection::Forward, false),
+            Some((at(0, 0), true))
+        );
+        assert_eq!(
+            find_in(contents, at(1, 0), SearchDirection::Forward, false),
+            Some((at(2, 0), false))
+        );
+    }
+
+    #[test]
+    fn backward_wraps_around_to_the_bottom() {
+        let contents = "ab ab\nx\nab";
+
+        assert_eq!(
+            find_in(contents, at(0, 3), SearchDirection::Backward, false),
+            Some((at(0, 0), false))
+        );
+        assert_eq!(
+            find_in(contents, at(0, 0), SearchDirection::Backward, false),
+            Some((at(2, 0), true))
+        );
+    }
+
+    #[test]
+    fn a_single_match_is_found_again_after_wrapping() {
+        for direction in [SearchDirection::Forward, SearchDirection::Backward] {
+            assert_eq!(
+                find_in("x ab x", at(0, 2), direction, false),
+                Some((at(0, 2), true))
+            );
+        }
+    }
+
+    #[test]
+    fn no_matches() {
+        assert_eq!(
+            find_in("a b\nba", at(0, 0), SearchDirection::Forward, true),
+            None
+        );
+        assert_eq!(
+            find(
+                &Text::from_file_contents("ab"),
+                "",
+                at(0, 0),
+                SearchDirection::Forward,
+                true
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn overlapping_matches_all_count() {
+        let text = Text::from_file_contents("aaa");
+
+        assert_eq!(
+            find(&text, "aa", at(0, 0), SearchDirection::Forward, false),
+            Some((at(0, 1), false))
+        );
+    }
+}
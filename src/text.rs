@@ -0,0 +1,260 @@
+use {
+    ropey::Rope,
+    std::{
+        borrow::Cow,
+        io::{self, Write},
+        ops::Range,
+    },
+};
+
+// A location in a buffer’s text. The column is a byte offset into the line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Position {
+    pub(crate) line: usize,
+    pub(crate) col: usize,
+}
+
+// The contents of a buffer, as a sequence of lines separated by line feeds. The text is kept in a
+// rope so that editing stays cheap no matter how large the file is or where in it the edit is
+// made: inserting or removing a line doesn’t involve shifting every line after it.
+//
+// There is always at least one line, even if it is empty, so that the cursor has somewhere to be.
+//
+// Files are written back with the line endings they were read with, so that saving a file only
+// changes the lines that were edited.
+#[derive(Debug, Default)]
+pub(crate) struct Text {
+    rope: Rope,
+    line_ending: LineEnding,
+    final_line_ending: FinalLineEnding,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum LineEnding {
+    #[default]
+    Lf,
+    // Windows-style, i.e. a carriage return followed by a line feed.
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+        }
+    }
+}
+
+// Whether the last line is followed by a line ending.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum FinalLineEnding {
+    Present,
+    Absent,
+    // New files end with a line ending as text files are expected to, unless they are empty.
+    #[default]
+    UnlessEmpty,
+}
+
+impl Text {
+    // The line ending that ends the last line of a file is implied by the format rather than being
+    // part of the text. Windows-style line endings are turned into line feeds, and turned back
+    // when writing. Files with a mixture of the two are written with whichever comes first.
+    pub(crate) fn from_file_contents(contents: &str) -> Self {
+        let line_ending = match contents.find('\n') {
+            Some(idx) if contents[..idx].ends_with('\r') => LineEnding::CrLf,
+            _ => LineEnding::Lf,
+        };
+
+        let (contents, final_line_ending) = match contents.strip_suffix(line_ending.as_str()) {
+            Some(contents) => (contents, FinalLineEnding::Present),
+            None => (contents, FinalLineEnding::Absent),
+        };
+
+        let rope = if contents.contains('\r') {
+            Rope::from_str(&contents.replace("\r\n", "\n"))
+        } else {
+            Rope::from_str(contents)
+        };
+
+        Self {
+            rope,
+            line_ending,
+            final_line_ending,
+        }
+    }
+
+    pub(crate) fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        for chunk in self.rope.chunks() {
+            match self.line_ending {
+                LineEnding::Lf => writer.write_all(chunk.as_bytes())?,
+                LineEnding::CrLf => writer.write_all(chunk.replace('\n', "\r\n").as_bytes())?,
+            }
+        }
+
+        let has_final_line_ending = match self.final_line_ending {
+            FinalLineEnding::Present => true,
+            FinalLineEnding::Absent => false,
+            FinalLineEnding::UnlessEmpty => self.rope.len_bytes() > 0,
+        };
+
+        if has_final_line_ending {
+            writer.write_all(self.line_ending.as_str().as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    // Gets the given line, without its line ending. This only allocates if the line happens to be
+    // split across several of the rope’s chunks.
+    pub(crate) fn line(&self, line_nr: usize) -> Cow<'_, str> {
+        let line: Cow<'_, str> = self.rope.line(line_nr).into();
+
+        match line {
+            Cow::Borrowed(line) => Cow::Borrowed(line.strip_suffix('\n').unwrap_or(line)),
+            Cow::Owned(mut line) => {
+                if line.ends_with('\n') {
+                    line.pop();
+                }
+
+                Cow::Owned(line)
+            }
+        }
+    }
+
+    // The length of the given line in bytes, without its line ending.
+    pub(crate) fn line_len(&self, line_nr: usize) -> usize {
+        let line_start = self.rope.line_to_byte(line_nr);
+
+        let line_end = if line_nr + 1 < self.len_lines() {
+            // Don’t count the line feed at the end of the line.
+            self.rope.line_to_byte(line_nr + 1) - 1
+        } else {
+            self.rope.len_bytes()
+        };
+
+        line_end - line_start
+    }
+
+    // Gets the given lines joined together with line feeds.
+    pub(crate) fn lines(&self, range: Range<usize>) -> String {
+        let start = self.rope.line_to_char(range.start);
+        let end = self.rope.line_to_char(range.end);
+
+        let mut lines = String::from(self.rope.slice(start..end));
+
+        if lines.ends_with('\n') {
+            lines.pop();
+        }
+
+        lines
+    }
+
+    pub(crate) fn slice(&self, start: Position, end: Position) -> String {
+        self.rope
+            .slice(self.char_idx(start)..self.char_idx(end))
+            .into()
+    }
+
+    // The whole text as a single string, for searching across lines.
+    pub(crate) fn contents(&self) -> String {
+        self.rope.to_string()
+    }
+
+    pub(crate) fn len_bytes(&self) -> usize {
+        self.rope.len_bytes()
+    }
+
+    // Converts a position into a byte offset from the start of the text, and back again.
+    pub(crate) fn byte_idx(&self, position: Position) -> usize {
+        self.rope.line_to_byte(position.line) + position.col
+    }
+
+    pub(crate) fn char_at(&self, byte_idx: usize) -> Option<char> {
+        self.rope.get_char(self.rope.byte_to_char(byte_idx))
+    }
+
+    pub(crate) fn char_before(&self, byte_idx: usize) -> Option<char> {
+        let char_idx = self.rope.byte_to_char(byte_idx);
+        char_idx.checked_sub(1).map(|idx| self.rope.char(idx))
+    }
+
+    pub(crate) fn position(&self, byte_idx: usize) -> Position {
+        let line = self.rope.byte_to_line(byte_idx);
+
+        Position {
+            line,
+            col: byte_idx - self.rope.line_to_byte(line),
+        }
+    }
+
+    // Inserts text (which can span several lines) at the given position.
+    pub(crate) fn insert(&mut self, at: Position, text: &str) {
+        self.rope.insert(self.char_idx(at), text);
+    }
+
+    pub(crate) fn remove(&mut self, start: Position, end: Position) {
+        self.rope.remove(self.char_idx(start)..self.char_idx(end));
+    }
+
+    // Ropes are indexed by chars, whereas positions are in bytes.
+    fn char_idx(&self, position: Position) -> usize {
+        self.rope.byte_to_char(self.byte_idx(position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(contents: &str) -> String {
+        let mut written = Vec::new();
+        Text::from_file_contents(contents)
+            .write_to(&mut written)
+            .unwrap();
+
+        String::from_utf8(written).unwrap()
+    }
+
+    #[test]
+    fn files_are_written_back_unchanged() {
+        for contents in [
+            "",
+            "\n",
+            "a\nb\n",
+            "a\nb",
+            "a\r\nb\r\n",
+            "a\r\nb",
+            "\r\n",
+            "a\rb\n",
+        ] {
+            assert_eq!(round_trip(contents), contents);
+        }
+    }
+
+    #[test]
+    fn windows_line_endings_are_not_part_of_the_text() {
+        let text = Text::from_file_contents("a\r\nb\r\n");
+
+        assert_eq!(text.len_lines(), 2);
+        assert_eq!(text.line(0), "a");
+        assert_eq!(text.contents(), "a\nb");
+    }
+
+    #[test]
+    fn new_files_end_with_a_line_ending_unless_empty() {
+        let mut written = Vec::new();
+        Text::default().write_to(&mut written).unwrap();
+        assert_eq!(written, b"");
+
+        let mut text = Text::default();
+        text.insert(Position { line: 0, col: 0 }, "a");
+        let mut written = Vec::new();
+        text.write_to(&mut written).unwrap();
+        assert_eq!(written, b"a\n");
+    }
+}
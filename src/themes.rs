@@ -1,3 +1,4 @@
+mod custom;
 mod dark_plus;
 mod dracula;
 mod gruvbox;
@@ -6,8 +7,9 @@ mod nord;
 mod seoul;
 mod solarized;
 
-#[allow(unused_imports)]
-pub(crate) use {
+use {
+    crate::config,
+    custom::CustomTheme,
     dark_plus::DarkPlus,
     dracula::Dracula,
     gruvbox::Gruvbox,
@@ -15,4 +17,310 @@ pub(crate) use {
     nord::Nord,
     seoul::Seoul,
     solarized::{SolarizedDark, SolarizedLight},
+    std::{fmt, fs, io, path::Path},
 };
+
+// The names of all built-in themes, in the order they are cycled through.
+const BUILTIN_NAMES: &[&str] = &[
+    "gruvbox",
+    "dark-plus",
+    "dracula",
+    "monokai",
+    "nord",
+    "seoul",
+    "solarized-dark",
+    "solarized-light",
+];
+
+// All the themes that can be selected: the built-in ones, plus any the user has defined in the
+// `themes` subdirectory of their config directory.
+#[derive(Debug, Default)]
+pub(crate) struct Themes {
+    custom: Vec<CustomTheme>,
+    // The names of the user’s themes that failed to load, along with why. One broken theme file
+    // only stops that theme from being selected.
+    broken: Vec<(String, String)>,
+}
+
+impl Themes {
+    pub(crate) fn load() -> anyhow::Result<Self> {
+        match config::dir() {
+            Some(dir) => Self::load_from(&dir.join("themes")),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn load_from(dir: &Path) -> anyhow::Result<Self> {
+        use anyhow::Context;
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", dir.display())),
+        };
+
+        let mut custom = Vec::new();
+        let mut broken = Vec::new();
+
+        for entry in entries {
+            let path = entry
+                .with_context(|| format!("failed to read {}", dir.display()))?
+                .path();
+
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "toml")
+            {
+                match CustomTheme::load(&path) {
+                    Ok(theme) => custom.push(theme),
+                    Err(e) => broken.push((custom::name_for(&path), format!("{:#}", e))),
+                }
+            }
+        }
+
+        // Directory listings come in no particular order, so sort the themes to make cycling
+        // through them predictable.
+        custom.sort_by(|a, b| a.name.cmp(&b.name));
+        broken.sort();
+
+        Ok(Self { custom, broken })
+    }
+
+    // Why the user’s themes that failed to load did so, if any did.
+    pub(crate) fn errors(&self) -> Option<String> {
+        if self.broken.is_empty() {
+            return None;
+        }
+
+        let errors: Vec<_> = self
+            .broken
+            .iter()
+            .map(|(_, error)| error.as_str())
+            .collect();
+        Some(errors.join("; "))
+    }
+
+    // User-defined themes take precedence over built-in themes of the same name.
+    pub(crate) fn get(&self, name: &str) -> anyhow::Result<Theme> {
+        if let Some(theme) = self.custom.iter().find(|theme| theme.name == name) {
+            return Ok(Theme {
+                name: name.to_string(),
+                inner: Box::new(theme.clone()),
+            });
+        }
+
+        // A broken theme file doesn’t fall back to the built-in theme it was meant to replace.
+        if let Some((_, error)) = self.broken.iter().find(|(broken, _)| broken == name) {
+            anyhow::bail!("{}", error);
+        }
+
+        let inner: Box<dyn dialect::Theme> = match name {
+            "gruvbox" => Box::new(Gruvbox),
+            "dark-plus" => Box::new(DarkPlus),
+            "dracula" => Box::new(Dracula),
+            "monokai" => Box::new(Monokai),
+            "nord" => Box::new(Nord),
+            "seoul" => Box::new(Seoul),
+            "solarized-dark" => Box::new(SolarizedDark),
+            "solarized-light" => Box::new(SolarizedLight),
+            _ => anyhow::bail!(
+                "unknown theme `{}` (available themes: {})",
+                name,
+                self.names().collect::<Vec<_>>().join(", ")
+            ),
+        };
+
+        Ok(Theme {
+            name: name.to_string(),
+            inner,
+        })
+    }
+
+    // The theme that comes after the given one, wrapping back around to the first theme at the
+    // end.
+    pub(crate) fn next(&self, theme: &Theme) -> Theme {
+        let names: Vec<_> = self.names().collect();
+
+        let idx = names
+            .iter()
+            .position(|name| *name == theme.name)
+            .map_or(0, |idx| (idx + 1) % names.len());
+
+        self.get(names[idx]).unwrap()
+    }
+
+    // The names of all themes that can be selected, in the order they are cycled through.
+    pub(crate) fn names(&self) -> impl Iterator<Item = &str> {
+        let custom_names = self
+            .custom
+            .iter()
+            .map(|theme| theme.name.as_str())
+            .filter(|name| !BUILTIN_NAMES.contains(name));
+
+        BUILTIN_NAMES
+            .iter()
+            .copied()
+            .filter(move |name| !self.broken.iter().any(|(broken, _)| broken == name))
+            .chain(custom_names)
+    }
+}
+
+// A theme along with the name it was selected by. Themes are chosen at runtime, so we can’t know
+// their concrete type.
+pub(crate) struct Theme {
+    pub(crate) name: String,
+    inner: Box<dyn dialect::Theme>,
+}
+
+// Themes only cover highlighting, so the styles for the rest of the interface are derived from
+// their default style.
+impl Theme {
+    // The status line of the focused view is drawn in the theme’s colors reversed, so that it
+    // stands out. Those of other views are only shaded slightly, to separate them from the text.
+    pub(crate) fn status_line_style(&self, is_focused: bool) -> ansi_term::Style {
+        use dialect::Theme;
+
+        let default_style = self.default_style();
+
+        let style = if is_focused {
+            dialect::ResolvedStyle {
+                fg_color: default_style.bg_color,
+                bg_color: default_style.fg_color,
+                is_bold: true,
+                is_italic: false,
+                is_underline: false,
+            }
+        } else {
+            dialect::ResolvedStyle {
+                bg_color: blend(default_style.bg_color, default_style.fg_color, 0.2),
+                ..default_style
+            }
+        };
+
+        style.into()
+    }
+
+    // Line numbers are faded into the background, except for the cursor’s line.
+    pub(crate) fn gutter_style(&self, is_cursor_line: bool) -> ansi_term::Style {
+        use dialect::Theme;
+
+        let default_style = self.default_style();
+
+        let fg_color = if is_cursor_line {
+            default_style.fg_color
+        } else {
+            blend(default_style.fg_color, default_style.bg_color, 0.5)
+        };
+
+        dialect::ResolvedStyle {
+            fg_color,
+            is_bold: false,
+            is_italic: false,
+            is_underline: false,
+            ..default_style
+        }
+        .into()
+    }
+
+    // Search matches keep their syntax highlighting, but are given a background tinted with the
+    // theme’s color for keywords, so that they stand out without clashing with the theme.
+    pub(crate) fn search_match_background(&self) -> ansi_term::Colour {
+        use dialect::Theme;
+
+        let default_style = self.default_style();
+        let accent = self
+            .style(dialect::HighlightGroup::CtrlFlowKeyword)
+            .fg_color
+            .unwrap_or(default_style.fg_color);
+
+        blend(default_style.bg_color, accent, 0.4).into()
+    }
+
+    // Selected text is shaded a little towards the foreground color, like the status lines of
+    // unfocused views.
+    pub(crate) fn selection_background(&self) -> ansi_term::Colour {
+        use dialect::Theme;
+
+        let default_style = self.default_style();
+
+        blend(default_style.bg_color, default_style.fg_color, 0.25).into()
+    }
+}
+
+// Mixes the given amount (between 0 and 1) of the second color into the first.
+fn blend(a: dialect::Rgb, b: dialect::Rgb, amount: f32) -> dialect::Rgb {
+    let mix = |a: u8, b: u8| (f32::from(a) * (1.0 - amount) + f32::from(b) * amount).round() as u8;
+
+    dialect::Rgb {
+        r: mix(a.r, b.r),
+        g: mix(a.g, b.g),
+        b: mix(a.b, b.b),
+    }
+}
+
+impl dialect::Theme for Theme {
+    fn default_style(&self) -> dialect::ResolvedStyle {
+        self.inner.default_style()
+    }
+
+    fn style(&self, group: dialect::HighlightGroup) -> dialect::Style {
+        self.inner.style(group)
+    }
+}
+
+impl fmt::Debug for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Theme").field("name", &self.name).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::env};
+
+    #[test]
+    fn broken_themes_are_reported_without_stopping_the_others_loading() {
+        let dir = env::temp_dir().join(format!("se-test-{}-themes", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("mine.toml"),
+            "[default]\nfg = \"#ffffff\"\nbg = \"#000000\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join("broken.toml"), "[default]\nfg = 1\n").unwrap();
+        fs::write(dir.join("gruvbox.toml"), "not toml").unwrap();
+
+        let themes = Themes::load_from(&dir).unwrap();
+        assert!(themes.get("mine").is_ok());
+        assert!(themes.get("dracula").is_ok());
+        assert!(!themes.names().any(|name| name == "broken"));
+
+        let error = themes.get("broken").unwrap_err().to_string();
+        assert!(error.starts_with(&format!(
+            "invalid theme {}",
+            dir.join("broken.toml").display()
+        )));
+        assert!(themes.get("gruvbox").is_err());
+        assert!(!themes.names().any(|name| name == "gruvbox"));
+
+        // Cycling through the themes skips the broken ones.
+        let mut theme = themes.get("mine").unwrap();
+        for _ in 0..BUILTIN_NAMES.len() {
+            theme = themes.next(&theme);
+        }
+        assert_eq!(theme.name, "mine");
+
+        let errors = themes.errors().unwrap();
+        assert!(errors.contains("broken.toml") && errors.contains("gruvbox.toml"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_missing_themes_directory_is_no_error() {
+        let themes = Themes::load_from(Path::new("/nonexistent/se/themes")).unwrap();
+        assert!(themes.errors().is_none());
+        assert_eq!(themes.names().count(), BUILTIN_NAMES.len());
+    }
+}
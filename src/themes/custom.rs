@@ -0,0 +1,280 @@
+// User-defined themes are written in TOML, and look like this:
+//
+//     [default]
+//     fg = "#ebdbb2"
+//     bg = "#1d2021"
+//
+//     [groups.comment]
+//     fg = "#928374"
+//     italic = true
+//
+// The `default` table is the style of unhighlighted text, and so must specify both colors. Each
+// table under `groups` styles one highlight group, and every one of its keys is optional.
+
+use {
+    anyhow::Context,
+    dialect::HighlightGroup,
+    std::{collections::HashMap, fs, path::Path},
+    toml::value::{Table, Value},
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct CustomTheme {
+    pub(crate) name: String,
+    default_style: dialect::ResolvedStyle,
+    styles: HashMap<HighlightGroup, dialect::Style>,
+}
+
+impl CustomTheme {
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let name = name_for(path);
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("failed to read theme {}", path.display()))?;
+
+        Self::parse(name, &source).with_context(|| format!("invalid theme {}", path.display()))
+    }
+
+    fn parse(name: String, source: &str) -> anyhow::Result<Self> {
+        let table: Table = toml::from_str(source)?;
+
+        for key in table.keys() {
+            if key != "default" && key != "groups" {
+                anyhow::bail!("`{}`: unknown key (expected `default` or `groups`)", key);
+            }
+        }
+
+        let default_table = match table.get("default") {
+            Some(default) => as_table(default, "default")?,
+            None => anyhow::bail!("`default`: missing table"),
+        };
+        let default_style = parse_style(default_table, "default")?;
+
+        let default_style = dialect::ResolvedStyle {
+            fg_color: default_style
+                .fg_color
+                .ok_or_else(|| anyhow::anyhow!("`default.fg`: missing color"))?,
+            bg_color: default_style
+                .bg_color
+                .ok_or_else(|| anyhow::anyhow!("`default.bg`: missing color"))?,
+            is_bold: default_style.is_bold,
+            is_italic: default_style.is_italic,
+            is_underline: default_style.is_underline,
+        };
+
+        let mut styles = HashMap::new();
+
+        if let Some(groups) = table.get("groups") {
+            for (group_name, style) in as_table(groups, "groups")? {
+                let key = format!("groups.{}", group_name);
+
+                let group = GROUPS
+                    .iter()
+                    .find(|(name, _)| name == group_name)
+                    .map(|(_, group)| *group)
+                    .ok_or_else(|| anyhow::anyhow!("`{}`: unknown highlight group", key))?;
+
+                styles.insert(group, parse_style(as_table(style, &key)?, &key)?);
+            }
+        }
+
+        Ok(Self {
+            name,
+            default_style,
+            styles,
+        })
+    }
+}
+
+impl dialect::Theme for CustomTheme {
+    fn default_style(&self) -> dialect::ResolvedStyle {
+        self.default_style
+    }
+
+    // Groups the theme doesn’t mention are displayed in the default style.
+    fn style(&self, group: HighlightGroup) -> dialect::Style {
+        self.styles.get(&group).copied().unwrap_or_default()
+    }
+}
+
+fn parse_style(table: &Table, key: &str) -> anyhow::Result<dialect::Style> {
+    let mut style = dialect::Style::new();
+
+    for (name, value) in table {
+        let key = format!("{}.{}", key, name);
+
+        match name.as_str() {
+            "fg" => style.fg_color = Some(parse_color(value, &key)?),
+            "bg" => style.bg_color = Some(parse_color(value, &key)?),
+            "bold" => style.is_bold = as_bool(value, &key)?,
+            "italic" => style.is_italic = as_bool(value, &key)?,
+            "underline" => style.is_underline = as_bool(value, &key)?,
+            _ => anyhow::bail!(
+                "`{}`: unknown key (expected `fg`, `bg`, `bold`, `italic` or `underline`)",
+                key
+            ),
+        }
+    }
+
+    Ok(style)
+}
+
+// Colors are written as hex triplets, e.g. `#1d2021`.
+fn parse_color(value: &Value, key: &str) -> anyhow::Result<dialect::Rgb> {
+    let invalid = || anyhow::anyhow!("`{}`: expected a color of the form \"#rrggbb\"", key);
+
+    let hex = value
+        .as_str()
+        .and_then(|s| s.strip_prefix('#'))
+        .filter(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(invalid)?;
+
+    let component = |idx| u8::from_str_radix(&hex[idx..idx + 2], 16).map_err(|_| invalid());
+
+    Ok(dialect::Rgb {
+        r: component(0)?,
+        g: component(2)?,
+        b: component(4)?,
+    })
+}
+
+fn as_table<'a>(value: &'a Value, key: &str) -> anyhow::Result<&'a Table> {
+    value
+        .as_table()
+        .ok_or_else(|| anyhow::anyhow!("`{}`: expected a table", key))
+}
+
+fn as_bool(value: &Value, key: &str) -> anyhow::Result<bool> {
+    value
+        .as_bool()
+        .ok_or_else(|| anyhow::anyhow!("`{}`: expected `true` or `false`", key))
+}
+
+// Themes are named after the file they are loaded from.
+pub(crate) fn name_for(path: &Path) -> String {
+    path.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+// The names highlight groups are referred to by in theme files.
+const GROUPS: &[(&str, HighlightGroup)] = &[
+    ("ctrl_flow_keyword", HighlightGroup::CtrlFlowKeyword),
+    ("other_keyword", HighlightGroup::OtherKeyword),
+    ("function_def", HighlightGroup::FunctionDef),
+    ("function_call", HighlightGroup::FunctionCall),
+    ("ty_def", HighlightGroup::TyDef),
+    ("ty_use", HighlightGroup::TyUse),
+    ("interface_def", HighlightGroup::InterfaceDef),
+    ("interface_use", HighlightGroup::InterfaceUse),
+    ("primitive_ty", HighlightGroup::PrimitiveTy),
+    ("variable_def", HighlightGroup::VariableDef),
+    ("variable_use", HighlightGroup::VariableUse),
+    ("member_def", HighlightGroup::MemberDef),
+    ("member_use", HighlightGroup::MemberUse),
+    ("constant_def", HighlightGroup::ConstantDef),
+    ("constant_use", HighlightGroup::ConstantUse),
+    ("module_def", HighlightGroup::ModuleDef),
+    ("module_use", HighlightGroup::ModuleUse),
+    ("macro_def", HighlightGroup::MacroDef),
+    ("macro_use", HighlightGroup::MacroUse),
+    ("special_ident_def", HighlightGroup::SpecialIdentDef),
+    ("special_ident_use", HighlightGroup::SpecialIdentUse),
+    ("function_param", HighlightGroup::FunctionParam),
+    ("number", HighlightGroup::Number),
+    ("string", HighlightGroup::String),
+    ("string_delimiter", HighlightGroup::StringDelimiter),
+    ("character", HighlightGroup::Character),
+    ("character_delimiter", HighlightGroup::CharacterDelimiter),
+    ("boolean", HighlightGroup::Boolean),
+    ("pre_proc", HighlightGroup::PreProc),
+    ("attribute", HighlightGroup::Attribute),
+    ("comment", HighlightGroup::Comment),
+    ("doc_comment", HighlightGroup::DocComment),
+    ("member_oper", HighlightGroup::MemberOper),
+    ("pointer_oper", HighlightGroup::PointerOper),
+    ("assign_oper", HighlightGroup::AssignOper),
+    ("binary_oper", HighlightGroup::BinaryOper),
+    ("other_oper", HighlightGroup::OtherOper),
+    ("delimiter", HighlightGroup::Delimiter),
+    ("separator", HighlightGroup::Separator),
+    ("terminator", HighlightGroup::Terminator),
+    ("error", HighlightGroup::Error),
+];
+
+#[cfg(test)]
+mod tests {
+    use {super::*, dialect::Theme};
+
+    fn parse(source: &str) -> anyhow::Result<CustomTheme> {
+        CustomTheme::parse("test".to_string(), source)
+    }
+
+    fn error(source: &str) -> String {
+        format!("{:#}", parse(source).unwrap_err())
+    }
+
+    const DEFAULT: &str = "[default]\nfg = \"#ebdbb2\"\nbg = \"#1d2021\"\n";
+
+    fn rgb(color: dialect::Rgb) -> (u8, u8, u8) {
+        (color.r, color.g, color.b)
+    }
+
+    #[test]
+    fn groups_are_styled_on_top_of_the_default() {
+        let theme = parse(&format!(
+            "{}[groups.comment]\nfg = \"#928374\"\nitalic = true\n",
+            DEFAULT
+        ))
+        .unwrap();
+
+        let default_style = theme.default_style();
+        assert_eq!(rgb(default_style.fg_color), (0xeb, 0xdb, 0xb2));
+        assert_eq!(rgb(default_style.bg_color), (0x1d, 0x20, 0x21));
+        assert!(!default_style.is_italic);
+
+        let comment = theme.style(HighlightGroup::Comment);
+        assert_eq!(comment.fg_color.map(rgb), Some((0x92, 0x83, 0x74)));
+        assert!(comment.is_italic);
+        assert!(theme.style(HighlightGroup::String).fg_color.is_none());
+    }
+
+    #[test]
+    fn the_default_needs_both_colors() {
+        assert_eq!(error(""), "`default`: missing table");
+        assert_eq!(error("default = 1"), "`default`: expected a table");
+        assert_eq!(
+            error("[default]\nfg = \"#ebdbb2\""),
+            "`default.bg`: missing color"
+        );
+    }
+
+    #[test]
+    fn errors_say_which_key_is_wrong() {
+        assert_eq!(
+            error(&format!("{}[colors]", DEFAULT)),
+            "`colors`: unknown key (expected `default` or `groups`)"
+        );
+        assert_eq!(
+            error(&format!("{}[groups.comments]", DEFAULT)),
+            "`groups.comments`: unknown highlight group"
+        );
+        assert_eq!(
+            error(&format!("{}[groups.comment]\nfg = \"928374\"", DEFAULT)),
+            "`groups.comment.fg`: expected a color of the form \"#rrggbb\""
+        );
+        assert_eq!(
+            error(&format!("{}[groups.comment]\nfg = \"#92837g\"", DEFAULT)),
+            "`groups.comment.fg`: expected a color of the form \"#rrggbb\""
+        );
+        assert_eq!(
+            error(&format!("{}[groups.comment]\nbold = \"yes\"", DEFAULT)),
+            "`groups.comment.bold`: expected `true` or `false`"
+        );
+        assert_eq!(
+            error(&format!("{}[groups.comment]\nsize = 2", DEFAULT)),
+            "`groups.comment.size`: unknown key (expected `fg`, `bg`, `bold`, `italic` or \
+             `underline`)"
+        );
+    }
+}
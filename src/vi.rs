@@ -0,0 +1,928 @@
+// Modal editing in the style of Vi, for those who prefer it (see `config.rs` for how to turn it on).
+//
+// In normal mode keys are commands: motions move the cursor, and operators (`d`, `c` and `y`)
+// followed by a motion act on the text the motion moves over. Both can be preceded by a count,
+// e.g. `3w` or `d2w`, and an operator typed twice acts on whole lines, e.g. `dd`. Insert mode
+// types text as usual until escape is pressed, and visual mode selects text for an operator to act
+// on. Keys Vi has no use for (e.g. C-s or M-s) are handled as they are outside of modal editing.
+//
+// Everything here works with byte offsets into the buffer’s text, since motions can cross line
+// boundaries. Motions only look at the characters around the cursor and the line it is on, so
+// they work on the buffer’s text where it is rather than on a copy of the whole of it.
+
+use {
+    crate::{buffer::Buffer, keymap::Action, register::Register},
+    crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
+    std::{borrow::Cow, ops::Range},
+    unicode_segmentation::UnicodeSegmentation,
+};
+
+// Counts are capped at this, which is still more lines than any file the editor can reasonably
+// open, so that typing too many digits can’t overflow or have a motion repeat practically forever.
+const MAX_COUNT: usize = 999_999;
+
+// Putting text several times over stops short of putting more than this many bytes.
+const MAX_PUT_LEN: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Normal,
+    Insert,
+    // Selecting text from the anchor to the cursor, inclusive of both. The anchor is a line and
+    // column rather than a byte offset, so that it stays on a character boundary whatever edits
+    // are made to the text while it is selected (e.g. undoing).
+    Visual { anchor: (usize, usize) },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    // Like `WordEnd`, except that the end of the word under the cursor counts even if the
+    // cursor is already on it.
+    CurrentWordEnd,
+    FirstLine,
+    LastLine,
+    // The motion an operator makes when it is typed twice.
+    CurrentLine,
+}
+
+// Where a motion goes, and how much of the text an operator acts on when combined with it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Extent {
+    // Up to but not including the target.
+    Exclusive,
+    // Up to and including the character at the target.
+    Inclusive,
+    // The whole of every line from the cursor’s to the target’s.
+    Linewise,
+}
+
+#[derive(Debug)]
+pub(crate) enum ViEvent {
+    Handled,
+    // The key is bound to something the editor as a whole does, e.g. `:` opening the command line.
+    Action(Action),
+    // The key has nothing to do with modal editing, and is handled as it would be otherwise.
+    Unhandled,
+}
+
+#[derive(Debug)]
+pub(crate) struct Vi {
+    mode: Mode,
+    // The count typed so far, and the operator (along with the count typed before it) waiting
+    // for a motion.
+    count: Option<usize>,
+    operator: Option<(Operator, Option<usize>)>,
+    // Whether `g` has been typed, which is the first half of `gg`.
+    is_g_pending: bool,
+}
+
+impl Default for Vi {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Normal,
+            count: None,
+            operator: None,
+            is_g_pending: false,
+        }
+    }
+}
+
+impl Vi {
+    // Shown on the message line when there is nothing else to show, as Vim does.
+    pub(crate) fn mode_name(&self) -> Option<&'static str> {
+        match self.mode {
+            Mode::Normal => None,
+            Mode::Insert => Some("-- INSERT --"),
+            Mode::Visual { .. } => Some("-- VISUAL --"),
+        }
+    }
+
+    pub(crate) fn is_selecting(&self) -> bool {
+        matches!(self.mode, Mode::Visual { .. })
+    }
+
+    // Selecting with the mouse goes into visual mode, as it does in Vim, from wherever the cursor
+    // is when the selection is started.
+    pub(crate) fn select(&mut self, buffer: &mut Buffer) {
+        if self.mode == Mode::Insert {
+            buffer.end_edit_group();
+        }
+
+        self.reset();
+        self.mode = Mode::Visual {
+            anchor: (buffer.line_nr(), buffer.col_nr()),
+        };
+    }
+
+    // Insert and visual mode only make sense in the buffer they were entered in, so moving to
+    // another one goes back to normal mode first. Insert mode’s edit group is ended on the buffer
+    // it was begun on, rather than left open for every later edit to be grouped into.
+    pub(crate) fn return_to_normal(&mut self, buffer: &mut Buffer) {
+        if self.mode == Mode::Insert {
+            buffer.end_edit_group();
+        }
+
+        self.reset();
+        self.mode = Mode::Normal;
+    }
+
+    pub(crate) fn deselect(&mut self) {
+        if self.is_selecting() {
+            self.reset();
+            self.mode = Mode::Normal;
+        }
+    }
+
+    // The text selected in visual mode, as byte offsets.
+    pub(crate) fn selection(&self, buffer: &Buffer) -> Option<Range<usize>> {
+        match self.mode {
+            Mode::Visual { anchor } => Some(inclusive_range(
+                buffer,
+                anchor_byte_idx(anchor, buffer),
+                buffer.cursor_byte_idx(),
+            )),
+            Mode::Normal | Mode::Insert => None,
+        }
+    }
+
+    pub(crate) fn handle_key(
+        &mut self,
+        key: KeyEvent,
+        buffer: &mut Buffer,
+        register: &mut Register,
+    ) -> ViEvent {
+        let event = match self.mode {
+            Mode::Insert if key.code == KeyCode::Esc => {
+                self.mode = Mode::Normal;
+                buffer.end_edit_group();
+
+                // Leaving insert mode moves back onto the last character typed.
+                if buffer.col_nr() > 0 {
+                    buffer.move_cursor_to(buffer.line_nr(), buffer.col_nr() - 1);
+                }
+
+                ViEvent::Handled
+            }
+            Mode::Insert => ViEvent::Unhandled,
+            Mode::Normal | Mode::Visual { .. } => self.handle_command_key(key, buffer, register),
+        };
+
+        self.clamp_cursor(buffer);
+
+        event
+    }
+
+    // Outside of insert mode the cursor is always on a character, rather than after the last one
+    // on the line. This has to be done again whenever the cursor is moved by something other than
+    // Vi, e.g. undoing.
+    pub(crate) fn clamp_cursor(&self, buffer: &mut Buffer) {
+        if self.mode != Mode::Insert {
+            let len = buffer.line_len(buffer.line_nr());
+
+            if len > 0 && buffer.col_nr() >= len {
+                buffer.move_cursor_to(buffer.line_nr(), len - 1);
+            }
+        }
+    }
+
+    fn handle_command_key(
+        &mut self,
+        key: KeyEvent,
+        buffer: &mut Buffer,
+        register: &mut Register,
+    ) -> ViEvent {
+        // Some keys outside of the letters do the same as letters in Vi.
+        let c = match (key.code, key.modifiers) {
+            (KeyCode::Char(c), KeyModifiers::NONE) | (KeyCode::Char(c), KeyModifiers::SHIFT) => c,
+            (KeyCode::Left, KeyModifiers::NONE) | (KeyCode::Backspace, KeyModifiers::NONE) => 'h',
+            (KeyCode::Right, KeyModifiers::NONE) => 'l',
+            (KeyCode::Up, KeyModifiers::NONE) => 'k',
+            (KeyCode::Down, KeyModifiers::NONE) | (KeyCode::Enter, KeyModifiers::NONE) => 'j',
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                self.reset();
+                return ViEvent::Action(Action::Redo);
+            }
+            // Escape cancels whatever has been typed so far. If there is nothing to cancel then it
+            // does what it would usually do.
+            (KeyCode::Esc, _) => {
+                let is_pending = self.count.is_some() || self.operator.is_some();
+                let is_visual = matches!(self.mode, Mode::Visual { .. });
+
+                self.reset();
+                self.mode = Mode::Normal;
+
+                return if is_pending || is_visual {
+                    ViEvent::Handled
+                } else {
+                    ViEvent::Unhandled
+                };
+            }
+            _ => {
+                self.reset();
+                return ViEvent::Unhandled;
+            }
+        };
+
+        // A zero that doesn’t continue a count moves to the start of the line.
+        if let Some(digit) = c.to_digit(10) {
+            if digit != 0 || self.count.is_some() {
+                self.count = Some((self.count.unwrap_or(0) * 10 + digit as usize).min(MAX_COUNT));
+                return ViEvent::Handled;
+            }
+        }
+
+        if self.is_g_pending {
+            self.is_g_pending = false;
+
+            if c == 'g' {
+                self.apply_motion(Motion::FirstLine, buffer, register);
+            } else {
+                self.reset();
+            }
+
+            return ViEvent::Handled;
+        }
+
+        let motion = match c {
+            'h' => Some(Motion::Left),
+            'l' => Some(Motion::Right),
+            'k' => Some(Motion::Up),
+            'j' => Some(Motion::Down),
+            'w' => Some(Motion::WordForward),
+            'b' => Some(Motion::WordBackward),
+            'e' => Some(Motion::WordEnd),
+            '0' => Some(Motion::LineStart),
+            '$' => Some(Motion::LineEnd),
+            'G' => Some(Motion::LastLine),
+            _ => None,
+        };
+
+        if let Some(motion) = motion {
+            self.apply_motion(motion, buffer, register);
+            return ViEvent::Handled;
+        }
+
+        if c == 'g' {
+            self.is_g_pending = true;
+            return ViEvent::Handled;
+        }
+
+        // Once an operator has been typed, only a motion or the same operator again can follow.
+        if let Some((operator, _)) = self.operator {
+            if operator_for(c) == Some(operator) {
+                self.apply_motion(Motion::CurrentLine, buffer, register);
+            } else {
+                self.reset();
+            }
+
+            return ViEvent::Handled;
+        }
+
+        if let Mode::Visual { anchor } = self.mode {
+            return self.handle_visual_key(c, anchor, buffer, register);
+        }
+
+        let count = self.count.take();
+
+        match c {
+            'd' | 'c' | 'y' => {
+                self.operator = Some((operator_for(c).unwrap(), count));
+                return ViEvent::Handled;
+            }
+            // `x`, `D` and `C` are shorthands for `dl`, `d$` and `c$`.
+            'x' => {
+                self.operator = Some((Operator::Delete, count));
+                self.apply_motion(Motion::Right, buffer, register);
+            }
+            'D' | 'C' => {
+                let operator = operator_for(c.to_ascii_lowercase()).unwrap();
+                self.operator = Some((operator, count));
+                self.apply_motion(Motion::LineEnd, buffer, register);
+            }
+            'i' => self.start_insert(buffer),
+            'a' => {
+                self.start_insert(buffer);
+                buffer.move_cursor_to(buffer.line_nr(), buffer.col_nr() + 1);
+            }
+            'I' => {
+                self.start_insert(buffer);
+                buffer.move_cursor_to_byte(first_non_blank(buffer, buffer.cursor_byte_idx()));
+            }
+            'A' => {
+                self.start_insert(buffer);
+                buffer.move_cursor_to(buffer.line_nr(), usize::MAX);
+            }
+            'o' => {
+                self.start_insert(buffer);
+                buffer.move_cursor_to(buffer.line_nr(), usize::MAX);
+                buffer.insert_newline();
+            }
+            'O' => {
+                self.start_insert(buffer);
+                buffer.move_cursor_to(buffer.line_nr(), 0);
+                buffer.insert_newline();
+                buffer.move_cursor_to(buffer.line_nr() - 1, 0);
+            }
+            'v' => {
+                self.mode = Mode::Visual {
+                    anchor: (buffer.line_nr(), buffer.col_nr()),
+                }
+            }
+            'p' => put(buffer, register, count.unwrap_or(1), true),
+            'P' => put(buffer, register, count.unwrap_or(1), false),
+            'u' => return ViEvent::Action(Action::Undo),
+            ':' => return ViEvent::Action(Action::CommandLine),
+            '/' => return ViEvent::Action(Action::Search),
+            'n' => return ViEvent::Action(Action::NextMatch),
+            'N' => return ViEvent::Action(Action::PreviousMatch),
+            _ => {}
+        }
+
+        ViEvent::Handled
+    }
+
+    // In visual mode operators act on the selection straight away, rather than waiting for a
+    // motion.
+    fn handle_visual_key(
+        &mut self,
+        c: char,
+        anchor: (usize, usize),
+        buffer: &mut Buffer,
+        register: &mut Register,
+    ) -> ViEvent {
+        self.count = None;
+
+        let operator = match c {
+            'd' | 'x' => Operator::Delete,
+            'c' => Operator::Change,
+            'y' => Operator::Yank,
+            'v' => {
+                self.mode = Mode::Normal;
+                return ViEvent::Handled;
+            }
+            ':' => return ViEvent::Action(Action::CommandLine),
+            _ => return ViEvent::Handled,
+        };
+
+        let range = inclusive_range(
+            buffer,
+            anchor_byte_idx(anchor, buffer),
+            buffer.cursor_byte_idx(),
+        );
+
+        self.mode = Mode::Normal;
+        self.operate(operator, range, false, buffer, register);
+
+        ViEvent::Handled
+    }
+
+    fn apply_motion(&mut self, motion: Motion, buffer: &mut Buffer, register: &mut Register) {
+        // Counts before the operator and before the motion multiply, e.g. `2d3w` deletes six
+        // words.
+        let operator = self.operator.take();
+        let count = match (operator.and_then(|(_, count)| count), self.count.take()) {
+            (Some(a), Some(b)) => Some((a * b).min(MAX_COUNT)),
+            (a, b) => a.or(b),
+        };
+        self.reset();
+
+        let from = buffer.cursor_byte_idx();
+
+        // `cw` changes up to the end of the word, rather than up to the start of the next one.
+        let motion = match (operator, motion) {
+            (Some((Operator::Change, _)), Motion::WordForward)
+                if buffer.char_at(from).is_some_and(|c| !c.is_whitespace()) =>
+            {
+                Motion::CurrentWordEnd
+            }
+            _ => motion,
+        };
+
+        let (mut target, extent) = self.target(motion, count, from, buffer);
+
+        let operator = match operator {
+            Some((operator, _)) => operator,
+            None => {
+                buffer.move_cursor_to_byte(target);
+                return;
+            }
+        };
+
+        // If the last word moved over ends a line, then operating on words stops at the end of that
+        // line rather than carrying on to the first word of the next.
+        let target_line_start = line_start(buffer, target);
+
+        if motion == Motion::WordForward
+            && target_line_start > from
+            && buffer.text_in(target_line_start..target).trim().is_empty()
+        {
+            target = target_line_start - 1;
+        }
+
+        let range = match extent {
+            Extent::Exclusive => from.min(target)..from.max(target),
+            Extent::Inclusive => inclusive_range(buffer, from, target),
+            Extent::Linewise => {
+                line_start(buffer, from.min(target))..line_end(buffer, from.max(target))
+            }
+        };
+
+        self.operate(
+            operator,
+            range,
+            extent == Extent::Linewise,
+            buffer,
+            register,
+        );
+    }
+
+    // Where the motion takes the cursor, repeated as many times as the count says.
+    fn target(
+        &self,
+        motion: Motion,
+        count: Option<usize>,
+        from: usize,
+        buffer: &Buffer,
+    ) -> (usize, Extent) {
+        let repeat = |step: fn(&Buffer, usize) -> usize| {
+            repeat_step(count.unwrap_or(1), from, |idx| step(buffer, idx))
+        };
+        let line_nr = buffer.line_nr();
+        let last_line = buffer.len_lines() - 1;
+        let line = |line_nr: usize| first_non_blank(buffer, buffer.byte_idx_at(line_nr, 0));
+
+        match motion {
+            Motion::Left => (repeat(previous_grapheme), Extent::Exclusive),
+            Motion::Right => (repeat(next_grapheme), Extent::Exclusive),
+            Motion::Up => {
+                let line_nr = line_nr.saturating_sub(count.unwrap_or(1));
+                (
+                    buffer.byte_idx_at(line_nr, buffer.col_nr()),
+                    Extent::Linewise,
+                )
+            }
+            Motion::Down => {
+                let line_nr = (line_nr + count.unwrap_or(1)).min(last_line);
+                (
+                    buffer.byte_idx_at(line_nr, buffer.col_nr()),
+                    Extent::Linewise,
+                )
+            }
+            Motion::WordForward => (repeat(next_word_start), Extent::Exclusive),
+            Motion::WordBackward => (repeat(previous_word_start), Extent::Exclusive),
+            Motion::WordEnd => (repeat(next_word_end), Extent::Inclusive),
+            Motion::CurrentWordEnd => {
+                let end = repeat_step(
+                    count.unwrap_or(1) - 1,
+                    current_word_end(buffer, from),
+                    |idx| next_word_end(buffer, idx),
+                );
+                (end, Extent::Inclusive)
+            }
+            Motion::LineStart => (line_start(buffer, from), Extent::Exclusive),
+            Motion::LineEnd => {
+                let line_nr = (line_nr + count.unwrap_or(1) - 1).min(last_line);
+                (buffer.byte_idx_at(line_nr, usize::MAX), Extent::Exclusive)
+            }
+            // With a count these go to that line, and otherwise to the first or last line.
+            Motion::FirstLine => (
+                line(count.map_or(0, |nr| nr.saturating_sub(1)).min(last_line)),
+                Extent::Linewise,
+            ),
+            Motion::LastLine => (
+                line(
+                    count
+                        .map_or(last_line, |nr| nr.saturating_sub(1))
+                        .min(last_line),
+                ),
+                Extent::Linewise,
+            ),
+            Motion::CurrentLine => (
+                line((line_nr + count.unwrap_or(1) - 1).min(last_line)),
+                Extent::Linewise,
+            ),
+        }
+    }
+
+    // Linewise ranges cover whole lines, but don’t include the line feed at the end of the last
+    // one.
+    fn operate(
+        &mut self,
+        operator: Operator,
+        range: Range<usize>,
+        is_linewise: bool,
+        buffer: &mut Buffer,
+        register: &mut Register,
+    ) {
+        // There is nothing to act on, e.g. `x` on an empty line, so leave the register alone.
+        if range.is_empty() && !is_linewise && operator != Operator::Change {
+            return;
+        }
+
+        let cursor = buffer.cursor_byte_idx();
+
+        register.set(buffer.text_in(range.clone()), is_linewise);
+
+        match operator {
+            // Yanking moves the cursor to the start of what was yanked, unless it was the lines
+            // from the cursor’s downwards.
+            Operator::Yank if is_linewise => {
+                if range.start < line_start(buffer, cursor) {
+                    buffer.move_cursor_to_byte(first_non_blank(buffer, range.start));
+                }
+            }
+            Operator::Yank => buffer.move_cursor_to_byte(range.start),
+            Operator::Delete if is_linewise => {
+                // Deleting lines takes one of the line feeds around them along with them, so that
+                // no empty line is left behind.
+                let range = if range.end < buffer.len_bytes() {
+                    range.start..range.end + 1
+                } else if range.start > 0 {
+                    range.start - 1..range.end
+                } else {
+                    range
+                };
+
+                buffer.replace(range.clone(), "");
+
+                let start = range.start.min(buffer.len_bytes());
+                buffer.move_cursor_to_byte(first_non_blank(buffer, start));
+            }
+            Operator::Delete => buffer.replace(range, ""),
+            // Changing lines leaves an empty line behind to type into.
+            Operator::Change => {
+                self.start_insert(buffer);
+                buffer.replace(range, "");
+            }
+        }
+    }
+
+    // Everything typed until returning to normal mode is undone in one go.
+    fn start_insert(&mut self, buffer: &mut Buffer) {
+        self.mode = Mode::Insert;
+        buffer.begin_edit_group();
+    }
+
+    fn reset(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.is_g_pending = false;
+    }
+}
+
+fn operator_for(c: char) -> Option<Operator> {
+    match c {
+        'd' => Some(Operator::Delete),
+        'c' => Some(Operator::Change),
+        'y' => Some(Operator::Yank),
+        _ => None,
+    }
+}
+
+// Puts the register’s text after or before the cursor, or after or before the cursor’s line if it
+// was taken from whole lines. The cursor ends up on the last character put, or on the first of the
+// lines put.
+fn put(buffer: &mut Buffer, register: &mut Register, count: usize, is_after: bool) {
+    let (text, is_linewise) = register.get();
+
+    if text.is_empty() && !is_linewise {
+        return;
+    }
+
+    let cursor = buffer.cursor_byte_idx();
+
+    let count = count.min(MAX_PUT_LEN / (text.len() + 1)).max(1);
+
+    if is_linewise {
+        let lines = vec![text; count].join("\n");
+
+        let at = if is_after {
+            let at = line_end(buffer, cursor);
+            buffer.replace(at..at, &format!("\n{}", lines));
+            at + 1
+        } else {
+            let at = line_start(buffer, cursor);
+            buffer.replace(at..at, &format!("{}\n", lines));
+            at
+        };
+
+        buffer.move_cursor_to_byte(first_non_blank(buffer, at));
+    } else {
+        let at = if is_after && cursor < line_end(buffer, cursor) {
+            next_grapheme(buffer, cursor)
+        } else {
+            cursor
+        };
+
+        buffer.replace(at..at, &text.repeat(count));
+
+        let end = buffer.cursor_byte_idx();
+        buffer.move_cursor_to_byte(previous_grapheme(buffer, end).max(at));
+    }
+}
+
+// Where the visual mode anchor is in the text as it is now, which might have fewer lines or shorter
+// ones than when it was set.
+fn anchor_byte_idx((line_nr, col_nr): (usize, usize), buffer: &Buffer) -> usize {
+    buffer.byte_idx_at(line_nr.min(buffer.len_lines() - 1), col_nr)
+}
+
+// Takes the given number of steps from a byte index, stopping early once a step gets nowhere
+// (e.g. at the end of the text).
+fn repeat_step(count: usize, from: usize, step: impl Fn(usize) -> usize) -> usize {
+    let mut idx = from;
+
+    for _ in 0..count {
+        let next = step(idx);
+
+        if next == idx {
+            break;
+        }
+
+        idx = next;
+    }
+
+    idx
+}
+
+// Vi considers words to be either runs of letters, digits and underscores, or runs of other
+// non-blank characters.
+#[derive(PartialEq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+// The word or run of blanks containing the given offset, which stays within its line. There is
+// nothing to select at the end of a line.
+pub(crate) fn word_at(s: &(impl Chars + ?Sized), idx: usize) -> Range<usize> {
+    let word_class = match s.char_at(idx).filter(|c| *c != '\n') {
+        Some(c) => class(c),
+        None => return idx..idx,
+    };
+    let is_in_word = |c: &char| *c != '\n' && class(*c) == word_class;
+
+    let mut start = idx;
+    while let Some(c) = s.char_before(start).filter(is_in_word) {
+        start -= c.len_utf8();
+    }
+
+    let mut end = idx;
+    while let Some(c) = s.char_at(end).filter(is_in_word) {
+        end += c.len_utf8();
+    }
+
+    start..end
+}
+
+// Moves past the rest of the word under the cursor and any blanks after it. An empty line counts
+// as a word of its own.
+fn next_word_start(s: &(impl Chars + ?Sized), mut idx: usize) -> usize {
+    if let Some(c) = s.char_at(idx) {
+        let start_class = class(c);
+
+        if start_class != CharClass::Blank {
+            while let Some(c) = s.char_at(idx).filter(|c| class(*c) == start_class) {
+                idx += c.len_utf8();
+            }
+        }
+    }
+
+    while let Some(c) = s.char_at(idx).filter(|c| c.is_whitespace()) {
+        idx += c.len_utf8();
+
+        if c == '\n' && s.char_at(idx) == Some('\n') {
+            break;
+        }
+    }
+
+    idx
+}
+
+// Moves back to the start of the previous word, or onto an empty line if there is one in between.
+fn previous_word_start(s: &(impl Chars + ?Sized), mut idx: usize) -> usize {
+    while let Some(c) = s.char_before(idx).filter(|c| c.is_whitespace()) {
+        idx -= c.len_utf8();
+
+        if c == '\n' && s.char_before(idx) == Some('\n') {
+            return idx;
+        }
+    }
+
+    if let Some(c) = s.char_before(idx) {
+        let end_class = class(c);
+
+        while let Some(c) = s.char_before(idx).filter(|c| class(*c) == end_class) {
+            idx -= c.len_utf8();
+        }
+    }
+
+    idx
+}
+
+fn next_word_end(s: &(impl Chars + ?Sized), mut idx: usize) -> usize {
+    if let Some(c) = s.char_at(idx) {
+        idx += c.len_utf8();
+    }
+
+    while let Some(c) = s.char_at(idx).filter(|c| c.is_whitespace()) {
+        idx += c.len_utf8();
+    }
+
+    current_word_end(s, idx)
+}
+
+fn current_word_end(s: &(impl Chars + ?Sized), mut idx: usize) -> usize {
+    if let Some(c) = s.char_at(idx) {
+        let word_class = class(c);
+        let mut next = idx + c.len_utf8();
+
+        while let Some(c) = s.char_at(next).filter(|c| class(*c) == word_class) {
+            idx = next;
+            next += c.len_utf8();
+        }
+    }
+
+    idx
+}
+
+// Moving left and right stays within the line.
+fn previous_grapheme(s: &(impl Chars + ?Sized), idx: usize) -> usize {
+    let (start, line) = s.line_at(idx);
+    line[..idx - start]
+        .grapheme_indices(true)
+        .next_back()
+        .map_or(idx, |(offset, _)| start + offset)
+}
+
+fn next_grapheme(s: &(impl Chars + ?Sized), idx: usize) -> usize {
+    let (start, line) = s.line_at(idx);
+    line[idx - start..]
+        .graphemes(true)
+        .next()
+        .map_or(idx, |grapheme| idx + grapheme.len())
+}
+
+fn line_start(s: &(impl Chars + ?Sized), idx: usize) -> usize {
+    s.line_at(idx).0
+}
+
+fn line_end(s: &(impl Chars + ?Sized), idx: usize) -> usize {
+    let (start, line) = s.line_at(idx);
+    start + line.len()
+}
+
+fn first_non_blank(s: &(impl Chars + ?Sized), idx: usize) -> usize {
+    let (start, line) = s.line_at(idx);
+    start + (line.len() - line.trim_start().len())
+}
+
+// The text between two offsets, including the character at whichever comes last. At the end of a
+// line that is its line feed.
+fn inclusive_range(s: &(impl Chars + ?Sized), a: usize, b: usize) -> Range<usize> {
+    let (start, end) = (a.min(b), a.max(b));
+    let end = match next_grapheme(s, end) {
+        next if next > end => next,
+        _ => end + s.char_at(end).map_or(0, char::len_utf8),
+    };
+
+    start..end
+}
+
+// What motions need to know about the text: the characters on either side of an offset, and the
+// line it is on. The buffer provides these without copying its text, and strings provide them for
+// testing.
+pub(crate) trait Chars {
+    fn char_at(&self, idx: usize) -> Option<char>;
+    fn char_before(&self, idx: usize) -> Option<char>;
+    // The line containing the given offset (without its line feed), and the offset it starts at.
+    fn line_at(&self, idx: usize) -> (usize, Cow<'_, str>);
+}
+
+impl Chars for Buffer {
+    fn char_at(&self, idx: usize) -> Option<char> {
+        Buffer::char_at(self, idx)
+    }
+
+    fn char_before(&self, idx: usize) -> Option<char> {
+        Buffer::char_before(self, idx)
+    }
+
+    fn line_at(&self, idx: usize) -> (usize, Cow<'_, str>) {
+        Buffer::line_at(self, idx)
+    }
+}
+
+impl Chars for str {
+    fn char_at(&self, idx: usize) -> Option<char> {
+        self[idx..].chars().next()
+    }
+
+    fn char_before(&self, idx: usize) -> Option<char> {
+        self[..idx].chars().next_back()
+    }
+
+    fn line_at(&self, idx: usize) -> (usize, Cow<'_, str>) {
+        let start = self[..idx].rfind('\n').map_or(0, |newline| newline + 1);
+        let end = self[idx..]
+            .find('\n')
+            .map_or(self.len(), |newline| idx + newline);
+
+        (start, Cow::Borrowed(&self[start..end]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every offset a motion stops at on its way through the text, until it gets nowhere.
+    fn stops(s: &str, from: usize, motion: fn(&str, usize) -> usize) -> Vec<usize> {
+        let mut stops = Vec::new();
+        let mut idx = from;
+
+        loop {
+            let next = motion(s, idx);
+
+            if next == idx {
+                return stops;
+            }
+
+            stops.push(next);
+            idx = next;
+        }
+    }
+
+    const TEXT: &str = "foo.bar  baz\n\nqux";
+
+    #[test]
+    fn words_are_letters_or_punctuation() {
+        assert_eq!(stops(TEXT, 0, next_word_start), [3, 4, 9, 13, 14, 17]);
+        assert_eq!(stops(TEXT, 0, next_word_end), [2, 3, 6, 11, 16, 17]);
+    }
+
+    #[test]
+    fn backwards_words_stop_on_empty_lines() {
+        assert_eq!(
+            stops(TEXT, TEXT.len(), previous_word_start),
+            [14, 13, 9, 4, 3, 0]
+        );
+    }
+
+    #[test]
+    fn current_word_end_includes_the_cursor() {
+        assert_eq!(current_word_end(TEXT, 4), 6);
+        assert_eq!(current_word_end(TEXT, 6), 6);
+        assert_eq!(next_word_end(TEXT, 6), 11);
+    }
+
+    #[test]
+    fn words_at_an_offset_stay_in_their_line() {
+        assert_eq!(word_at(TEXT, 5), 4..7);
+        assert_eq!(word_at(TEXT, 7), 7..9);
+        assert_eq!(word_at(TEXT, 3), 3..4);
+        assert_eq!(word_at(TEXT, 12), 12..12);
+        assert_eq!(word_at("héllo wörld", 3), 0..6);
+    }
+
+    #[test]
+    fn huge_counts_stop_at_the_end_of_the_text() {
+        let s = "one two three";
+
+        assert_eq!(
+            repeat_step(MAX_COUNT, 0, |idx| next_word_start(s, idx)),
+            s.len()
+        );
+        assert_eq!(
+            repeat_step(MAX_COUNT, 8, |idx| previous_grapheme(s, idx)),
+            0
+        );
+    }
+}
@@ -0,0 +1,485 @@
+use {
+    crate::{buffer::Buffer, graphemes, layout::Rect, render, themes::Theme},
+    regex::Regex,
+    std::{
+        collections::HashMap,
+        convert::TryInto,
+        io::{self, Write},
+        ops::Range,
+        str::FromStr,
+    },
+};
+
+// What the gutter to the left of the text shows, if it is shown at all. Relative line numbers count
+// the distance from the cursor’s line, which itself shows its absolute line number.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    Relative,
+}
+
+impl FromStr for LineNumbers {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "off" => Ok(Self::Off),
+            "absolute" => Ok(Self::Absolute),
+            "relative" => Ok(Self::Relative),
+            _ => anyhow::bail!(
+                "unknown line number style `{}` (expected `off`, `absolute` or `relative`)",
+                s
+            ),
+        }
+    }
+}
+
+// A window onto a buffer, which scrolls independently of any other views onto the same buffer.
+//
+// The buffer only has a single cursor, which belongs to whichever view is focused. Every view keeps
+// its own copy, which for the focused view is kept in sync after each key press, and for the other
+// views is put back into the buffer when they get focus again.
+#[derive(Debug, Clone)]
+pub(crate) struct View {
+    pub(crate) buffer_idx: usize,
+    line_nr: usize,
+    col_nr: usize,
+    top_line: usize,
+    left_col: usize,
+    rect: Rect,
+    line_numbers: LineNumbers,
+    // How far the view was scrolled (`top_line` and `left_col`) in each of the other buffers it
+    // has shown, so that switching back to one of them doesn’t lose the user’s place.
+    scroll_positions: HashMap<usize, (usize, usize)>,
+}
+
+impl View {
+    // New views start at the top of the buffer.
+    pub(crate) fn new(buffer_idx: usize) -> Self {
+        Self {
+            buffer_idx,
+            line_nr: 0,
+            col_nr: 0,
+            top_line: 0,
+            left_col: 0,
+            rect: Rect::default(),
+            line_numbers: LineNumbers::Off,
+            scroll_positions: HashMap::new(),
+        }
+    }
+
+    // Switches the view to another buffer, picking up where that buffer’s cursor was left, and
+    // scrolled as it was when the view last showed it.
+    pub(crate) fn show(&mut self, buffer_idx: usize, buffer: &Buffer) {
+        self.scroll_positions
+            .insert(self.buffer_idx, (self.top_line, self.left_col));
+
+        let (top_line, left_col) = self.scroll_positions.remove(&buffer_idx).unwrap_or((0, 0));
+
+        self.buffer_idx = buffer_idx;
+        self.top_line = top_line;
+        self.left_col = left_col;
+        self.save_cursor(buffer);
+    }
+
+    pub(crate) fn save_cursor(&mut self, buffer: &Buffer) {
+        self.line_nr = buffer.line_nr();
+        self.col_nr = buffer.col_nr();
+        self.scroll_to_cursor(buffer);
+    }
+
+    // Edits made through another view onto the same buffer might have left this view’s cursor
+    // past the end of the text, so it is clamped (and the view scrolled to it) first.
+    pub(crate) fn restore_cursor(&mut self, buffer: &mut Buffer) {
+        self.scroll_to_cursor(buffer);
+        buffer.move_cursor_to(self.line_nr, self.col_nr);
+    }
+
+    // Moves the view to a new place on screen. If its size has changed then the cursor might not be
+    // visible any more, so we scroll to it.
+    pub(crate) fn set_rect(&mut self, rect: Rect, buffer: &Buffer) {
+        if rect != self.rect {
+            self.rect = rect;
+            self.scroll_to_cursor(buffer);
+        }
+    }
+
+    // Showing or hiding the gutter changes how much room there is for the text.
+    pub(crate) fn set_line_numbers(&mut self, line_numbers: LineNumbers, buffer: &Buffer) {
+        if line_numbers != self.line_numbers {
+            self.line_numbers = line_numbers;
+            self.scroll_to_cursor(buffer);
+        }
+    }
+
+    // Edits made through another view onto the same buffer can leave this view’s cursor past the
+    // end of the text, so it is clamped before being used.
+    fn scroll_to_cursor(&mut self, buffer: &Buffer) {
+        self.line_nr = self.line_nr.min(buffer.len_lines() - 1);
+        self.col_nr = self.col_nr.min(buffer.line_len(self.line_nr));
+
+        self.scroll_lines();
+        self.scroll_cols(buffer);
+    }
+
+    // The bottom line of the view is taken up by its status line, so the text gets all the others.
+    fn text_lines(&self) -> u16 {
+        self.rect.lines.saturating_sub(1)
+    }
+
+    // A view with no room at all still scrolls as if it had a single line and column, so that the
+    // cursor is never above or to the left of it.
+    pub(crate) fn window_lines(&self) -> usize {
+        usize::from(self.text_lines()).max(1)
+    }
+
+    fn window_cols(&self, buffer: &Buffer) -> usize {
+        (usize::from(self.rect.cols) - self.gutter_width(buffer)).max(1)
+    }
+
+    // The gutter is wide enough for the largest line number in the buffer, plus a space to
+    // separate it from the text. It never takes up more than the whole view.
+    fn gutter_width(&self, buffer: &Buffer) -> usize {
+        let width = match self.line_numbers {
+            LineNumbers::Off => 0,
+            LineNumbers::Absolute | LineNumbers::Relative => {
+                buffer.len_lines().to_string().len().max(3) + 1
+            }
+        };
+
+        width.min(usize::from(self.rect.cols))
+    }
+
+    fn scroll_lines(&mut self) {
+        if self.line_nr < self.top_line {
+            self.top_line = self.line_nr;
+        } else if self.line_nr >= self.top_line + self.window_lines() {
+            self.top_line = self.line_nr - self.window_lines() + 1;
+        }
+    }
+
+    // Horizontal scrolling works in terminal columns, since wide characters (e.g. CJK or emoji)
+    // take up two of them.
+    fn scroll_cols(&mut self, buffer: &Buffer) {
+        let display_col = buffer.display_col(self.line_nr, self.col_nr);
+
+        // Make sure that the whole of the grapheme under the cursor fits on screen. At the end of
+        // the line the cursor still takes up a column.
+        let cursor_width = buffer
+            .grapheme_width(self.line_nr, self.col_nr)
+            .unwrap_or(1)
+            .max(1);
+
+        if display_col < self.left_col {
+            self.left_col = display_col;
+        } else if display_col + cursor_width > self.left_col + self.window_cols(buffer) {
+            self.left_col = (display_col + cursor_width).saturating_sub(self.window_cols(buffer));
+        }
+    }
+
+    // Draws the visible part of the buffer into the view’s rectangle, with the gutter to its left
+    // and the status line below it. Any matches of the given regular expression are highlighted,
+    // as is the selection (given as byte offsets into the buffer). Lines past the end of the
+    // buffer, and the parts of lines past their end, are filled with blanks so that nothing from
+    // the last redraw is left behind.
+    pub(crate) fn draw(
+        &self,
+        stdout: &mut io::Stdout,
+        buffer: &mut Buffer,
+        theme: &Theme,
+        is_focused: bool,
+        highlighted: Option<&Regex>,
+        selection: Option<&Range<usize>>,
+    ) -> anyhow::Result<()> {
+        use {
+            crossterm::{cursor, queue},
+            dialect::Theme,
+        };
+
+        let Rect { x, y, cols, .. } = self.rect;
+        let lines = self.text_lines();
+        let cols = usize::from(cols);
+        let gutter_width = self.gutter_width(buffer);
+        let text_cols = cols - gutter_width;
+        let default_style: ansi_term::Style = theme.default_style().into();
+        let len_lines = buffer.len_lines();
+
+        // Matches and the selection are found before highlighting, since the highlighted lines
+        // borrow the buffer.
+        let visible_lines = self.top_line..(self.top_line + usize::from(lines)).min(len_lines);
+        let search_matches: Vec<_> = visible_lines
+            .clone()
+            .map(|line_nr| {
+                highlighted.map_or_else(Vec::new, |regex| buffer.matches_in_line(line_nr, regex))
+            })
+            .collect();
+        let selected: Vec<_> = visible_lines
+            .map(|line_nr| {
+                selection
+                    .map(|selection| buffer.range_in_line(line_nr, selection))
+                    .filter(|range| !range.is_empty())
+            })
+            .collect();
+        let search_match_background = theme.search_match_background();
+        let selection_background = theme.selection_background();
+
+        // Only the lines that are on screen are highlighted.
+        let displayed_lines =
+            buffer.highlighted_lines(self.top_line..self.top_line + usize::from(lines), theme);
+
+        for row in 0..lines {
+            let line_nr = self.top_line + usize::from(row);
+
+            queue!(stdout, cursor::MoveTo(x, y + row))?;
+
+            if gutter_width > 0 {
+                let gutter_style = theme.gutter_style(line_nr == self.line_nr);
+                let gutter = if line_nr < len_lines {
+                    let number = match self.line_numbers {
+                        LineNumbers::Relative if line_nr != self.line_nr => {
+                            line_nr.abs_diff(self.line_nr)
+                        }
+                        _ => line_nr + 1,
+                    };
+
+                    format!("{:>1$} ", number, gutter_width - 1)
+                } else {
+                    " ".repeat(gutter_width)
+                };
+
+                write!(
+                    stdout,
+                    "{}{}{}",
+                    default_style.infix(gutter_style),
+                    graphemes::clip(&gutter, 0, gutter_width),
+                    gutter_style.infix(default_style)
+                )?;
+            }
+
+            let (line, line_width) = match displayed_lines.get(usize::from(row)) {
+                Some(line) => {
+                    let row = usize::from(row);
+                    let line = render::overlay(line, &search_matches[row], |style| {
+                        style.on(search_match_background)
+                    });
+                    let line = render::overlay(&line, selected[row].as_slice(), |style| {
+                        style.on(selection_background)
+                    });
+
+                    // Start drawing lines at the leftmost column that is currently being
+                    // displayed, and truncate them if they don’t fit in the view. This has to
+                    // happen before the line is converted to escape sequences, since those don’t
+                    // take up any columns on screen.
+                    let line = render::clip(&render::expand(&line), self.left_col, text_cols);
+                    let line_width = line
+                        .iter()
+                        .map(|span| graphemes::display_width(&span.text))
+                        .sum();
+
+                    (render::to_ansi(&line, default_style), line_width)
+                }
+                None => (String::new(), 0),
+            };
+
+            stdout.write_all(line.as_bytes())?;
+            stdout.write_all(" ".repeat(text_cols - line_width).as_bytes())?;
+        }
+
+        if self.rect.lines > 0 {
+            let status_line = graphemes::clip(&self.status_line(buffer), 0, cols);
+            let status_line_width = graphemes::display_width(&status_line);
+            let status_line_style = theme.status_line_style(is_focused);
+
+            queue!(stdout, cursor::MoveTo(x, y + lines))?;
+            write!(
+                stdout,
+                "{}{}",
+                default_style.infix(status_line_style),
+                status_line
+            )?;
+            write!(
+                stdout,
+                "{}{}",
+                " ".repeat(cols - status_line_width),
+                status_line_style.infix(default_style)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // The path and whether there are unsaved edits go on the left, and everything else goes on
+    // the right. Lines and columns are counted from one, as they are everywhere else.
+    fn status_line(&self, buffer: &Buffer) -> String {
+        let left = format!(
+            " {}{}",
+            buffer.path().display(),
+            if buffer.is_modified() { " [+]" } else { "" }
+        );
+        let right = format!(
+            "{}  {}:{}  {} line{} ",
+            buffer.language_name(),
+            self.line_nr + 1,
+            self.col_nr + 1,
+            buffer.len_lines(),
+            if buffer.len_lines() == 1 { "" } else { "s" }
+        );
+
+        // If there isn’t room for both then the right-hand side gets cut off, so at least the path
+        // is visible.
+        let padding = usize::from(self.rect.cols)
+            .saturating_sub(graphemes::display_width(&left) + graphemes::display_width(&right));
+
+        format!("{}{}{}", left, " ".repeat(padding.max(1)), right)
+    }
+
+    // Where the cursor is on screen, as a column and a line. Scrolling with the mouse can leave
+    // the cursor outside the view, in which case it isn’t anywhere on screen.
+    pub(crate) fn cursor_screen_position(&self, buffer: &Buffer) -> Option<(u16, u16)> {
+        if self.line_nr < self.top_line || self.line_nr >= self.top_line + self.window_lines() {
+            return None;
+        }
+
+        let col: u16 = (self.gutter_width(buffer) + buffer.display_col(self.line_nr, self.col_nr)
+            - self.left_col)
+            .try_into()
+            .unwrap();
+        let line: u16 = (self.line_nr - self.top_line).try_into().unwrap();
+
+        Some((self.rect.x + col, self.rect.y + line))
+    }
+
+    // Whether the given point on screen is within the view, including its status line.
+    pub(crate) fn contains(&self, x: u16, y: u16) -> bool {
+        let Rect {
+            x: left,
+            y: top,
+            cols,
+            lines,
+        } = self.rect;
+
+        (left..left + cols).contains(&x) && (top..top + lines).contains(&y)
+    }
+
+    pub(crate) fn is_on_status_line(&self, y: u16) -> bool {
+        y == self.rect.y + self.text_lines()
+    }
+
+    // The line and column in the buffer that are displayed at the given point on screen. Points
+    // outside the text (e.g. when dragging past the edge of the view) are taken to be at the
+    // nearest edge of it, and points in the gutter at the start of the line.
+    pub(crate) fn position_at(&self, x: u16, y: u16, buffer: &Buffer) -> (usize, usize) {
+        let row = usize::from(y.saturating_sub(self.rect.y)).min(self.window_lines() - 1);
+        let line_nr = (self.top_line + row).min(buffer.len_lines() - 1);
+
+        let col = usize::from(x.saturating_sub(self.rect.x))
+            .saturating_sub(self.gutter_width(buffer))
+            .min(self.window_cols(buffer) - 1);
+        let col_nr = buffer.col_at_display_col(line_nr, self.left_col + col);
+
+        (line_nr, col_nr)
+    }
+
+    // Moves the view up (for negative amounts) or down by the given number of lines, leaving the
+    // cursor where it is. The last line of the buffer can be scrolled up to the top of the view,
+    // but no further.
+    pub(crate) fn scroll(&mut self, lines: isize, buffer: &Buffer) {
+        self.top_line = self
+            .top_line
+            .saturating_add_signed(lines)
+            .min(buffer.len_lines() - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A new buffer with the given number of numbered lines.
+    fn buffer(len_lines: usize) -> Buffer {
+        let mut buffer = Buffer::new("test.txt").unwrap();
+        let contents: Vec<_> = (1..=len_lines).map(|nr| nr.to_string()).collect();
+        buffer.replace(0..0, &contents.join("\n"));
+        buffer.move_cursor_to(0, 0);
+
+        buffer
+    }
+
+    // A view ten lines of text tall, plus its status line.
+    fn view(buffer_idx: usize, buffer: &Buffer) -> View {
+        let mut view = View::new(buffer_idx);
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            cols: 20,
+            lines: 11,
+        };
+        view.set_rect(rect, buffer);
+
+        view
+    }
+
+    #[test]
+    fn views_scroll_to_keep_the_cursor_visible() {
+        let mut buffer = buffer(100);
+        let mut view = view(0, &buffer);
+
+        buffer.move_cursor_to(50, 0);
+        view.save_cursor(&buffer);
+        assert_eq!(view.top_line, 41);
+        assert_eq!(view.cursor_screen_position(&buffer), Some((0, 9)));
+
+        buffer.move_cursor_to(45, 0);
+        view.save_cursor(&buffer);
+        assert_eq!(view.top_line, 41);
+    }
+
+    #[test]
+    fn switching_back_to_a_buffer_keeps_its_scroll_position() {
+        let (mut a, b) = (buffer(100), buffer(5));
+        let mut view = view(0, &a);
+
+        a.move_cursor_to(99, 0);
+        view.save_cursor(&a);
+        a.move_cursor_to(95, 0);
+        view.save_cursor(&a);
+        assert_eq!(view.top_line, 90);
+
+        view.show(1, &b);
+        assert_eq!(view.top_line, 0);
+
+        view.show(0, &a);
+        assert_eq!(view.top_line, 90);
+        assert_eq!(view.cursor_screen_position(&a), Some((0, 5)));
+    }
+
+    #[test]
+    fn cursors_left_past_the_end_are_clamped() {
+        let mut buffer = buffer(100);
+        let mut view = view(0, &buffer);
+
+        buffer.move_cursor_to(99, 0);
+        view.save_cursor(&buffer);
+
+        // Another view onto the same buffer deletes most of it.
+        let end = buffer.len_bytes();
+        buffer.replace(3..end, "");
+        view.set_line_numbers(LineNumbers::Absolute, &buffer);
+
+        assert_eq!((view.line_nr, view.col_nr), (1, 0));
+        assert_eq!(view.cursor_screen_position(&buffer), Some((4, 0)));
+    }
+
+    #[test]
+    fn positions_on_screen_are_clamped_to_the_text() {
+        let buffer = buffer(3);
+        let view = view(0, &buffer);
+
+        assert_eq!(view.position_at(0, 1, &buffer), (1, 0));
+        assert_eq!(view.position_at(15, 1, &buffer), (1, 1));
+        assert_eq!(view.position_at(0, 8, &buffer), (2, 0));
+        assert!(view.is_on_status_line(10));
+        assert!(!view.contains(20, 0));
+    }
+}